@@ -3,28 +3,29 @@
 # Create models directory if it doesn't exist
 mkdir -p models
 
-# Download U2Net model
-echo "Downloading U2Net model..."
-if command -v wget >/dev/null 2>&1; then
-    wget -O models/u2net.onnx https://github.com/danielgatis/rembg/releases/download/v0.0.0/u2net.onnx
-elif command -v curl >/dev/null 2>&1; then
-    curl -L https://github.com/danielgatis/rembg/releases/download/v0.0.0/u2net.onnx -o models/u2net.onnx
-else
-    echo "Error: Neither wget nor curl is installed. Please install one of them and try again."
-    exit 1
+BASE_URL="https://github.com/danielgatis/rembg/releases/download/v0.0.0"
+
+# Download the models passed as arguments, or the default set
+MODELS=("$@")
+if [ ${#MODELS[@]} -eq 0 ]; then
+    MODELS=(u2net u2netp silueta isnet-general-use)
 fi
 
-echo "Model downloaded successfully to models/u2net.onnx"
+download_model() {
+    local name=$1
+    echo "Downloading ${name} model..."
+    if command -v wget >/dev/null 2>&1; then
+        wget -O "models/${name}.onnx" "${BASE_URL}/${name}.onnx"
+    elif command -v curl >/dev/null 2>&1; then
+        curl -L "${BASE_URL}/${name}.onnx" -o "models/${name}.onnx"
+    else
+        echo "Error: Neither wget nor curl is installed. Please install one of them and try again."
+        exit 1
+    fi
 
-# Download Silueta model
-echo "Downloading Silueta model..."
-if command -v wget >/dev/null 2>&1; then
-    wget -O models/silueta.onnx https://github.com/danielgatis/rembg/releases/download/v0.0.0/silueta.onnx
-elif command -v curl >/dev/null 2>&1; then
-    curl -L https://github.com/danielgatis/rembg/releases/download/v0.0.0/silueta.onnx -o models/silueta.onnx
-else
-    echo "Error: Neither wget nor curl is installed. Please install one of them and try again."
-    exit 1
-fi
+    echo "Model downloaded successfully to models/${name}.onnx"
+}
 
-echo "Model downloaded successfully to models/silueta.onnx"
+for model in "${MODELS[@]}"; do
+    download_model "$model"
+done
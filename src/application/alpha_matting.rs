@@ -0,0 +1,168 @@
+use image::DynamicImage;
+use rayon::prelude::*;
+use super::constants::alpha_matting::*;
+use super::guided_filter::{box_filter, GuidedFilter};
+use super::options::AlphaMattingOptions;
+
+/// Trimap-based alpha matting. Confident foreground and background are taken from the mask
+/// and eroded; alpha in the remaining unknown band is solved from local foreground and
+/// background color estimates, then smoothed with a guided filter.
+pub struct AlphaMatting;
+
+impl AlphaMatting {
+    pub fn apply(img: &DynamicImage, alpha: &[f32], options: &AlphaMattingOptions) -> Vec<f32> {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let trimap = Self::trimap(alpha, width, height, options);
+
+        let rgb: Vec<[f32; 3]> = img.to_rgb8()
+            .as_raw()
+            .chunks_exact(3)
+            .map(|pixel| [pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0, pixel[2] as f32 / 255.0])
+            .collect();
+
+        let solved = Self::solve(&rgb, &trimap, width, height);
+
+        let guide = GuidedFilter::guide_from_image(img);
+        let smoothed = GuidedFilter::new(SMOOTHING_RADIUS, SMOOTHING_EPSILON).filter(&guide, &solved, width, height);
+
+        trimap.par_iter()
+            .zip(smoothed.par_iter())
+            .map(|(&region, &value)| match region {
+                TRIMAP_FOREGROUND => 1.0,
+                TRIMAP_BACKGROUND => 0.0,
+                _ => value,
+            })
+            .collect()
+    }
+
+    /// Thresholds the mask into foreground, background and unknown, eroding both known regions
+    /// by `erode_size` so the unknown band covers the whole transition
+    pub fn trimap(alpha: &[f32], width: usize, height: usize, options: &AlphaMattingOptions) -> Vec<u8> {
+        let foreground_threshold = options.foreground_threshold as f32 / 255.0;
+        let background_threshold = options.background_threshold as f32 / 255.0;
+
+        let is_foreground: Vec<f32> = alpha.iter().map(|&a| f32::from(u8::from(a > foreground_threshold))).collect();
+        let is_background: Vec<f32> = alpha.iter().map(|&a| f32::from(u8::from(a < background_threshold))).collect();
+
+        let radius = options.erode_size as usize / 2;
+        let foreground = Self::erode(&is_foreground, width, height, radius);
+        let background = Self::erode(&is_background, width, height, radius);
+
+        foreground.iter()
+            .zip(&background)
+            .map(|(&fg, &bg)| {
+                if fg {
+                    TRIMAP_FOREGROUND
+                } else if bg {
+                    TRIMAP_BACKGROUND
+                } else {
+                    TRIMAP_UNKNOWN
+                }
+            })
+            .collect()
+    }
+
+    /// Binary erosion with a square window clipped to the image, so the image border
+    /// itself does not erode a region
+    fn erode(mask: &[f32], width: usize, height: usize, radius: usize) -> Vec<bool> {
+        if radius == 0 {
+            return mask.iter().map(|&value| value > 0.0).collect();
+        }
+
+        box_filter(mask, width, height, radius)
+            .par_iter()
+            .map(|&mean| mean >= 1.0 - f32::EPSILON)
+            .collect()
+    }
+
+    /// Estimates alpha for unknown pixels as the projection of the pixel color onto the line
+    /// between local foreground and background mean colors. Windows grow until both regions
+    /// are represented, falling back to the global means.
+    fn solve(rgb: &[[f32; 3]], trimap: &[u8], width: usize, height: usize) -> Vec<f32> {
+        let mut alpha: Vec<f32> = trimap.iter()
+            .map(|&region| if region == TRIMAP_FOREGROUND { 1.0 } else { 0.0 })
+            .collect();
+        let mut unresolved: Vec<usize> = (0..trimap.len()).filter(|&i| trimap[i] == TRIMAP_UNKNOWN).collect();
+        if unresolved.is_empty() {
+            return alpha;
+        }
+
+        let foreground: Vec<f32> = trimap.iter().map(|&r| f32::from(u8::from(r == TRIMAP_FOREGROUND))).collect();
+        let background: Vec<f32> = trimap.iter().map(|&r| f32::from(u8::from(r == TRIMAP_BACKGROUND))).collect();
+
+        for &radius in SAMPLE_RADII {
+            if unresolved.is_empty() {
+                break;
+            }
+
+            let fg_means = LocalColorMeans::new(rgb, &foreground, width, height, radius);
+            let bg_means = LocalColorMeans::new(rgb, &background, width, height, radius);
+
+            unresolved.retain(|&i| match (fg_means.get(i), bg_means.get(i)) {
+                (Some(fg), Some(bg)) => {
+                    alpha[i] = Self::project(rgb[i], fg, bg);
+                    false
+                }
+                _ => true,
+            });
+        }
+
+        if !unresolved.is_empty() {
+            let global_fg = LocalColorMeans::global(rgb, &foreground);
+            let global_bg = LocalColorMeans::global(rgb, &background);
+            for i in unresolved {
+                alpha[i] = match (global_fg, global_bg) {
+                    (Some(fg), Some(bg)) => Self::project(rgb[i], fg, bg),
+                    (Some(_), None) => 1.0,
+                    _ => 0.0,
+                };
+            }
+        }
+
+        alpha
+    }
+
+    fn project(color: [f32; 3], foreground: [f32; 3], background: [f32; 3]) -> f32 {
+        let mut numerator = 0.0;
+        let mut denominator = PROJECTION_EPSILON;
+        for c in 0..3 {
+            let fb = foreground[c] - background[c];
+            numerator += (color[c] - background[c]) * fb;
+            denominator += fb * fb;
+        }
+        (numerator / denominator).clamp(0.0, 1.0)
+    }
+}
+
+/// Mean color of the pixels selected by a mask within a window around each pixel
+struct LocalColorMeans {
+    channels: [Vec<f32>; 3],
+    weight: Vec<f32>,
+}
+
+impl LocalColorMeans {
+    fn new(rgb: &[[f32; 3]], mask: &[f32], width: usize, height: usize, radius: usize) -> Self {
+        let channels = std::array::from_fn(|c| {
+            let weighted: Vec<f32> = rgb.iter().zip(mask).map(|(color, &m)| color[c] * m).collect();
+            box_filter(&weighted, width, height, radius)
+        });
+        Self { channels, weight: box_filter(mask, width, height, radius) }
+    }
+
+    fn get(&self, i: usize) -> Option<[f32; 3]> {
+        let weight = self.weight[i];
+        (weight > MIN_SAMPLE_WEIGHT).then(|| std::array::from_fn(|c| self.channels[c][i] / weight))
+    }
+
+    fn global(rgb: &[[f32; 3]], mask: &[f32]) -> Option<[f32; 3]> {
+        let mut sum = [0f64; 3];
+        let mut weight = 0f64;
+        for (color, &m) in rgb.iter().zip(mask) {
+            for c in 0..3 {
+                sum[c] += (color[c] * m) as f64;
+            }
+            weight += m as f64;
+        }
+        (weight > 0.0).then(|| std::array::from_fn(|c| (sum[c] / weight) as f32))
+    }
+}
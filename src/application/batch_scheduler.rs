@@ -0,0 +1,133 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use ndarray::{Array4, ArrayView4, Axis};
+use crate::domain::AppError;
+use super::inference_v2::ModelMetadata;
+use super::session_pool::SessionPool;
+
+type BatchReply = mpsc::Sender<Result<Vec<f32>, AppError>>;
+
+struct BatchRequest {
+    input: Array4<f32>,
+    reply: BatchReply,
+}
+
+/// Collects single-image inferences from concurrent callers into `[N, 3, H, W]` batches.
+/// Each pool session gets a worker thread; a worker waits for a first request, then keeps
+/// collecting for up to `window` or until `max_batch_size` requests are queued.
+pub struct BatchScheduler {
+    pool: Arc<SessionPool>,
+    sender: Option<mpsc::Sender<BatchRequest>>,
+}
+
+impl BatchScheduler {
+    /// Batching is disabled when `max_batch_size` is 1 or the model has a fixed batch axis;
+    /// inference then goes straight to the pool.
+    pub fn new(model_name: &str, pool: SessionPool, max_batch_size: usize, window: Duration) -> Result<Self, AppError> {
+        let pool = Arc::new(pool);
+        if max_batch_size <= 1 || !pool.metadata().dynamic_batch {
+            return Ok(Self { pool, sender: None });
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for index in 0..pool.size() {
+            let pool = Arc::clone(&pool);
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("batch-{}-{}", model_name, index))
+                .spawn(move || Self::worker(&pool, &receiver, max_batch_size, window))
+                .map_err(|e| AppError::ModelError(e.to_string()))?;
+        }
+
+        Ok(Self { pool, sender: Some(sender) })
+    }
+
+    pub fn metadata(&self) -> &ModelMetadata {
+        self.pool.metadata()
+    }
+
+    /// Runs one `[1, 3, H, W]` input, possibly batched with other callers, and returns its mask
+    pub fn run(&self, input: ArrayView4<f32>) -> Result<Vec<f32>, AppError> {
+        let Some(sender) = &self.sender else {
+            return self.pool.run(input);
+        };
+
+        let (reply, response) = mpsc::channel();
+        sender
+            .send(BatchRequest { input: input.to_owned(), reply })
+            .map_err(|_| AppError::ModelError("Batch scheduler is not running".to_string()))?;
+
+        response
+            .recv()
+            .map_err(|_| AppError::ModelError("Batch scheduler dropped the request".to_string()))?
+    }
+
+    fn worker(pool: &SessionPool, receiver: &Mutex<mpsc::Receiver<BatchRequest>>, max_batch_size: usize, window: Duration) {
+        loop {
+            let batch = {
+                let receiver = receiver.lock().unwrap_or_else(|e| e.into_inner());
+                let Ok(first) = receiver.recv() else {
+                    return;
+                };
+
+                let deadline = Instant::now() + window;
+                let mut batch = vec![first];
+                while batch.len() < max_batch_size {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match receiver.recv_timeout(remaining) {
+                        Ok(request) => batch.push(request),
+                        Err(_) => break,
+                    }
+                }
+                batch
+            };
+
+            Self::run_batch(pool, batch);
+        }
+    }
+
+    fn run_batch(pool: &SessionPool, batch: Vec<BatchRequest>) {
+        let batch_size = batch.len();
+        let result = if batch_size == 1 {
+            pool.run(batch[0].input.view())
+        } else {
+            let views: Vec<_> = batch.iter().map(|request| request.input.view()).collect();
+            ndarray::concatenate(Axis(0), &views)
+                .map_err(|e| AppError::ModelError(e.to_string()))
+                .and_then(|stacked| pool.run(stacked.view()))
+        };
+        tracing::debug!("Ran inference batch of {}", batch_size);
+
+        match result.and_then(|outputs| Self::split_masks(outputs, batch_size)) {
+            Ok(masks) => {
+                for (request, mask) in batch.into_iter().zip(masks) {
+                    let _ = request.reply.send(Ok(mask));
+                }
+            }
+            Err(e) => {
+                for request in batch {
+                    let _ = request.reply.send(Err(e.clone()));
+                }
+            }
+        }
+    }
+
+    fn split_masks(outputs: Vec<f32>, batch_size: usize) -> Result<Vec<Vec<f32>>, AppError> {
+        if batch_size == 1 {
+            return Ok(vec![outputs]);
+        }
+
+        let mask_len = outputs.len() / batch_size;
+        if mask_len == 0 || mask_len * batch_size != outputs.len() {
+            return Err(AppError::ModelError(format!(
+                "Cannot split {} output values into a batch of {}",
+                outputs.len(), batch_size
+            )));
+        }
+
+        Ok(outputs.chunks_exact(mask_len).map(<[f32]>::to_vec).collect())
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::Cursor;
+use bytes::Bytes;
+use image::DynamicImage;
+use img_parts::{DynImage, ImageEXIF, ImageICC};
+use qcms::{DataType, Intent, Profile, Transform};
+use crate::domain::AppError;
+use super::constants::metadata::*;
+
+/// ICC profile and descriptive EXIF tags carried from the input into the output. Reading and
+/// embedding work on JPEG, PNG and WebP containers; other formats have none.
+#[derive(Debug, Clone, Default)]
+pub struct ImageMetadata {
+    pub icc_profile: Option<Bytes>,
+    /// EXIF block holding only the carried tags
+    pub exif: Option<Bytes>,
+}
+
+impl ImageMetadata {
+    pub fn read(data: &Bytes) -> Self {
+        let icc_profile = DynImage::from_bytes(data.clone())
+            .ok()
+            .flatten()
+            .and_then(|container| container.icc_profile());
+        Self { icc_profile, exif: Self::carried_exif(data) }
+    }
+
+    /// Whether a profile describes RGB pixels; CMYK and grayscale profiles do not apply to the output
+    pub fn is_rgb_profile(icc_profile: &[u8]) -> bool {
+        icc_profile.get(ICC_COLOR_SPACE_OFFSET..ICC_COLOR_SPACE_OFFSET + ICC_COLOR_SPACE_RGB.len())
+            == Some(ICC_COLOR_SPACE_RGB)
+    }
+
+    /// Writes the profile and EXIF into an encoded image; formats without support are returned as is
+    pub fn embed(&self, encoded: Vec<u8>) -> Result<Vec<u8>, AppError> {
+        if self.icc_profile.is_none() && self.exif.is_none() {
+            return Ok(encoded);
+        }
+
+        let encoded = Bytes::from(encoded);
+        let mut container = match DynImage::from_bytes(encoded.clone()) {
+            Ok(Some(container)) => container,
+            Ok(None) => return Ok(encoded.to_vec()),
+            Err(e) => return Err(AppError::ImageProcessingError(e.to_string())),
+        };
+        container.set_icc_profile(self.icc_profile.clone());
+        container.set_exif(self.exif.clone());
+        Ok(container.encoder().bytes().to_vec())
+    }
+
+    /// Rebuilds an EXIF block with only `CARRIED_EXIF_TAGS` from the primary image
+    fn carried_exif(data: &[u8]) -> Option<Bytes> {
+        let exif = exif::Reader::new().read_from_container(&mut Cursor::new(data)).ok()?;
+        let fields: Vec<&exif::Field> = CARRIED_EXIF_TAGS
+            .iter()
+            .filter_map(|&tag| exif.get_field(tag, exif::In::PRIMARY))
+            .collect();
+        if fields.is_empty() {
+            return None;
+        }
+
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut output = Cursor::new(Vec::new());
+        writer.write(&mut output, exif.little_endian()).ok()?;
+        Some(Bytes::from(output.into_inner()))
+    }
+}
+
+/// Converts pixels from an embedded ICC profile to sRGB
+pub struct ColorConverter;
+
+impl ColorConverter {
+    /// Images whose profile is not an RGB profile or cannot be parsed are returned unchanged.
+    /// qcms only transforms 8-bit pixels, so converted 16-bit images are reduced to 8 bits.
+    pub fn to_srgb(img: DynamicImage, icc_profile: &[u8]) -> DynamicImage {
+        if !ImageMetadata::is_rgb_profile(icc_profile) {
+            return img;
+        }
+        let Some(input) = Profile::new_from_slice(icc_profile, false) else {
+            tracing::warn!("Ignoring unreadable ICC profile");
+            return img;
+        };
+        if img.color().bytes_per_pixel() > img.color().channel_count() {
+            tracing::debug!("Converting a {:?} image to sRGB at 8 bits per channel", img.color());
+        }
+        let output = Profile::new_sRGB();
+
+        if img.color().has_alpha() {
+            let Some(transform) = Transform::new(&input, &output, DataType::RGBA8, Intent::Perceptual) else {
+                return img;
+            };
+            let mut rgba = img.to_rgba8();
+            transform.apply(&mut rgba);
+            DynamicImage::ImageRgba8(rgba)
+        } else {
+            let Some(transform) = Transform::new(&input, &output, DataType::RGB8, Intent::Perceptual) else {
+                return img;
+            };
+            let mut rgb = img.to_rgb8();
+            transform.apply(&mut rgb);
+            DynamicImage::ImageRgb8(rgb)
+        }
+    }
+
+    /// Transform from the CMYK pixels of a JPEG, holding ink amounts (0 is no ink), to sRGB.
+    /// `None` when the profile is not a usable CMYK profile.
+    pub fn cmyk_transform(icc_profile: &[u8]) -> Option<Transform> {
+        let input = Profile::new_from_slice(icc_profile, false)?;
+        Transform::new_to(&input, &Profile::new_sRGB(), DataType::CMYK, DataType::RGB8, Intent::Perceptual)
+    }
+}
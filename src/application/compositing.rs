@@ -0,0 +1,312 @@
+use image::{imageops::FilterType, DynamicImage, RgbImage};
+use rayon::prelude::*;
+use crate::domain::AppError;
+use super::constants::compositing::*;
+use super::decoding::InputDecoder;
+use super::guided_filter::box_filter;
+use super::options::{BackgroundFit, BackgroundOptions, Gradient};
+
+/// Replaces the transparent background with a color, gradient, image or a blurred copy of the
+/// original background, producing an opaque result
+pub struct BackgroundCompositor;
+
+impl BackgroundCompositor {
+    /// `original` is the input image, `foreground` the colors used for the subject
+    /// (the original or its decontaminated version)
+    pub fn composite(
+        original: &DynamicImage,
+        foreground: &DynamicImage,
+        alpha: &[f32],
+        options: &BackgroundOptions,
+        decoder: &InputDecoder,
+    ) -> Result<RgbImage, AppError> {
+        let background = match options.blur_radius {
+            Some(radius) if options.image.is_none() => Self::render_blurred(original, alpha, radius),
+            _ => Self::render_background(original.width(), original.height(), options, decoder)?,
+        };
+        let foreground = foreground.to_rgb8();
+        let (width, height) = foreground.dimensions();
+
+        let mut output = RgbImage::new(width, height);
+        output.par_chunks_exact_mut(3)
+            .zip(foreground.par_chunks_exact(3))
+            .zip(background.par_chunks_exact(3))
+            .enumerate()
+            .for_each(|(i, ((out, fg), bg))| {
+                let a = alpha[i];
+                for c in 0..3 {
+                    out[c] = (fg[c] as f32 * a + bg[c] as f32 * (1.0 - a)).round() as u8;
+                }
+            });
+
+        Ok(output)
+    }
+
+    /// Background image wins over a gradient, which wins over a solid color; a color or
+    /// gradient also fills the area left uncovered by a `contain` fitted image.
+    fn render_background(
+        width: u32,
+        height: u32,
+        options: &BackgroundOptions,
+        decoder: &InputDecoder,
+    ) -> Result<RgbImage, AppError> {
+        let fill = match (&options.gradient, options.color) {
+            (Some(gradient), _) => Self::render_gradient(width, height, gradient),
+            (None, Some(color)) => RgbImage::from_pixel(width, height, image::Rgb(color)),
+            (None, None) => RgbImage::from_pixel(width, height, image::Rgb(DEFAULT_FILL_COLOR)),
+        };
+
+        let Some(image_data) = &options.image else {
+            return Ok(fill);
+        };
+
+        let background = decoder.decode(image_data).map_err(|e| match e {
+            AppError::ImageProcessingError(message) => {
+                AppError::ImageProcessingError(format!("Invalid background image: {}", message))
+            }
+            e => e,
+        })?;
+        Ok(Self::fit_image(&background, fill, options.fit))
+    }
+
+    /// Blurs the original background with a Gaussian of standard deviation `radius`. Pixels are
+    /// weighted by `1 - alpha` so the subject does not bleed into the blurred background, and by
+    /// the source alpha so transparent areas (such as a crop extending past the image) do not either.
+    fn render_blurred(original: &DynamicImage, alpha: &[f32], radius: f32) -> RgbImage {
+        let (width, height) = (original.width() as usize, original.height() as usize);
+        let rgba = original.to_rgba8();
+        let weight: Vec<f32> = rgba.as_raw()
+            .par_chunks_exact(4)
+            .zip(alpha.par_iter())
+            .map(|(pixel, a)| (1.0 - a) * pixel[3] as f32 / 255.0)
+            .collect();
+        let blurred_weight = Self::gaussian_blur(&weight, width, height, radius);
+
+        let channels: [Vec<f32>; 3] = std::array::from_fn(|c| {
+            let weighted: Vec<f32> = rgba.as_raw()
+                .par_chunks_exact(4)
+                .zip(weight.par_iter())
+                .map(|(pixel, w)| pixel[c] as f32 * w)
+                .collect();
+            Self::gaussian_blur(&weighted, width, height, radius)
+        });
+
+        let mut output = RgbImage::new(width as u32, height as u32);
+        output.par_chunks_exact_mut(3)
+            .zip(rgba.par_chunks_exact(4))
+            .enumerate()
+            .for_each(|(i, (out, original))| {
+                let w = blurred_weight[i];
+                for c in 0..3 {
+                    // Where the background is hidden everywhere nearby, keep the original pixel
+                    out[c] = if w > BLUR_MIN_WEIGHT {
+                        (channels[c][i] / w).round().clamp(0.0, 255.0) as u8
+                    } else {
+                        original[c]
+                    };
+                }
+            });
+        output
+    }
+
+    /// Gaussian blur approximated by successive box filters (Kovesi, "Fast almost-Gaussian filtering")
+    fn gaussian_blur(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+        // Past the longest side the blur is already a plain average, and the box widths stay bounded
+        let sigma = sigma.min(width.max(height) as f32);
+        let passes = BLUR_BOX_PASSES as f32;
+        let ideal_width = (12.0 * sigma * sigma / passes + 1.0).sqrt();
+        let mut lower = (ideal_width.floor() as usize).max(1);
+        if lower & 1 == 0 {
+            lower -= 1;
+        }
+        let lower_f = lower as f32;
+        let lower_passes = ((12.0 * sigma * sigma - passes * lower_f * lower_f - 4.0 * passes * lower_f - 3.0 * passes)
+            / (-4.0 * lower_f - 4.0))
+            .round()
+            .max(0.0) as usize;
+
+        (0..BLUR_BOX_PASSES).fold(src.to_vec(), |blurred, pass| {
+            let box_width = if pass < lower_passes { lower } else { lower + 2 };
+            box_filter(&blurred, width, height, box_width / 2)
+        })
+    }
+
+    fn render_gradient(width: u32, height: u32, gradient: &Gradient) -> RgbImage {
+        let angle = gradient.angle_degrees.to_radians();
+        let (dx, dy) = (angle.cos(), angle.sin());
+
+        // Project every pixel on the gradient direction and normalize over the image extent
+        let corners = [(0.0, 0.0), (width as f32, 0.0), (0.0, height as f32), (width as f32, height as f32)];
+        let projections = corners.map(|(x, y)| x * dx + y * dy);
+        let min = projections.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = projections.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let range = (max - min).max(f32::EPSILON);
+
+        let mut output = RgbImage::new(width, height);
+        output.par_chunks_exact_mut(3).enumerate().for_each(|(i, pixel)| {
+            let x = (i % width as usize) as f32 + 0.5;
+            let y = (i / width as usize) as f32 + 0.5;
+            let t = ((x * dx + y * dy - min) / range).clamp(0.0, 1.0);
+            for (c, value) in pixel.iter_mut().enumerate() {
+                *value = (gradient.from[c] as f32 * (1.0 - t) + gradient.to[c] as f32 * t).round() as u8;
+            }
+        });
+        output
+    }
+
+    fn fit_image(background: &DynamicImage, mut canvas: RgbImage, fit: BackgroundFit) -> RgbImage {
+        let (width, height) = canvas.dimensions();
+        let fitted = FittedBackground::new((background.width(), background.height()), (width, height), fit);
+        let scaled = fitted.render(background);
+
+        // Center the scaled image; contain leaves the fill visible around it
+        let offset_x = (width - fitted.scaled_width) / 2;
+        let offset_y = (height - fitted.scaled_height) / 2;
+        image::imageops::replace(&mut canvas, &scaled, offset_x as i64, offset_y as i64);
+        canvas
+    }
+}
+
+/// Part of a background image shown on the canvas, in source pixels, and the size it is scaled
+/// to. Cover keeps only the centered source area that lands on the canvas, so the scaled image
+/// never exceeds the canvas whatever the aspect ratio of the background.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FittedBackground {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    scale: f32,
+    scaled_width: u32,
+    scaled_height: u32,
+}
+
+impl FittedBackground {
+    fn new((source_width, source_height): (u32, u32), (width, height): (u32, u32), fit: BackgroundFit) -> Self {
+        let scale_x = width as f32 / source_width as f32;
+        let scale_y = height as f32 / source_height as f32;
+        let scale = match fit {
+            BackgroundFit::Cover => scale_x.max(scale_y),
+            BackgroundFit::Contain => scale_x.min(scale_y),
+        };
+
+        let visible_width = (width as f32 / scale).min(source_width as f32);
+        let visible_height = (height as f32 / scale).min(source_height as f32);
+        Self {
+            x: (source_width as f32 - visible_width) / 2.0,
+            y: (source_height as f32 - visible_height) / 2.0,
+            width: visible_width,
+            height: visible_height,
+            scale,
+            scaled_width: ((visible_width * scale).round() as u32).clamp(1, width),
+            scaled_height: ((visible_height * scale).round() as u32).clamp(1, height),
+        }
+    }
+
+    /// Shrinking resizes the visible crop with a filter that averages the pixels it drops;
+    /// enlarging samples the source bilinearly, which keeps the sub-pixel offset of the crop
+    fn render(&self, background: &DynamicImage) -> RgbImage {
+        if self.scale < 1.0 {
+            let x = (self.x.round() as u32).min(background.width() - 1);
+            let y = (self.y.round() as u32).min(background.height() - 1);
+            let width = (self.width.round() as u32).clamp(1, background.width() - x);
+            let height = (self.height.round() as u32).clamp(1, background.height() - y);
+            return background
+                .crop_imm(x, y, width, height)
+                .resize_exact(self.scaled_width, self.scaled_height, FilterType::Triangle)
+                .to_rgb8();
+        }
+
+        let source = background.to_rgb8();
+        let (max_x, max_y) = ((source.width() - 1) as f32, (source.height() - 1) as f32);
+        let mut output = RgbImage::new(self.scaled_width, self.scaled_height);
+        output.par_chunks_exact_mut(3).enumerate().for_each(|(i, pixel)| {
+            let column = (i % self.scaled_width as usize) as f32;
+            let row = (i / self.scaled_width as usize) as f32;
+            let source_x = (self.x + (column + 0.5) / self.scale - 0.5).clamp(0.0, max_x);
+            let source_y = (self.y + (row + 0.5) / self.scale - 0.5).clamp(0.0, max_y);
+            if let Some(sample) = image::imageops::interpolate_bilinear(&source, source_x, source_y) {
+                pixel.copy_from_slice(&sample.0);
+            }
+        });
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+    use super::*;
+
+    const RED: Rgb<u8> = Rgb([255, 0, 0]);
+    const GREEN: Rgb<u8> = Rgb([0, 255, 0]);
+    const BLUE: Rgb<u8> = Rgb([0, 0, 255]);
+
+    /// 2x1 background, red on the left and blue on the right, fitted onto a green 4x4 canvas
+    fn fit(fit: BackgroundFit) -> RgbImage {
+        let background = DynamicImage::ImageRgb8(RgbImage::from_fn(2, 1, |x, _| if x == 0 { RED } else { BLUE }));
+        BackgroundCompositor::fit_image(&background, RgbImage::from_pixel(4, 4, GREEN), fit)
+    }
+
+    fn is_red(pixel: &Rgb<u8>) -> bool {
+        pixel[0] > 200 && pixel[1] < 50 && pixel[2] < 50
+    }
+
+    fn is_blue(pixel: &Rgb<u8>) -> bool {
+        pixel[0] < 50 && pixel[1] < 50 && pixel[2] > 200
+    }
+
+    #[test]
+    fn contain_letterboxes_the_background() {
+        let canvas = fit(BackgroundFit::Contain);
+        for y in 0..4 {
+            let expected = (1..3).contains(&y);
+            assert_eq!(is_red(canvas.get_pixel(0, y)), expected, "row {}", y);
+            assert_eq!(is_blue(canvas.get_pixel(3, y)), expected, "row {}", y);
+            if !expected {
+                assert!(canvas.rows().nth(y as usize).unwrap().all(|pixel| *pixel == GREEN), "row {}", y);
+            }
+        }
+    }
+
+    #[test]
+    fn cover_fills_the_canvas() {
+        let canvas = fit(BackgroundFit::Cover);
+        assert!(!canvas.pixels().any(|pixel| *pixel == GREEN));
+        for y in 0..4 {
+            assert!(is_red(canvas.get_pixel(0, y)), "row {}", y);
+            assert!(is_blue(canvas.get_pixel(3, y)), "row {}", y);
+        }
+    }
+
+    #[test]
+    fn cover_resizes_only_the_visible_area() {
+        let fitted = FittedBackground::new((16384, 1), (1000, 1000), BackgroundFit::Cover);
+        assert_eq!((fitted.x, fitted.width, fitted.scale), (8191.5, 1.0, 1000.0));
+        assert_eq!((fitted.scaled_width, fitted.scaled_height), (1000, 1000));
+
+        for (source, canvas) in [((16384, 1), (1000, 1000)), ((1, 16384), (1000, 10)), ((3, 2), (4096, 4096))] {
+            for fit in [BackgroundFit::Cover, BackgroundFit::Contain] {
+                let fitted = FittedBackground::new(source, canvas, fit);
+                assert!(fitted.x + fitted.width <= source.0 as f32 && fitted.y + fitted.height <= source.1 as f32);
+                assert!(fitted.scaled_width <= canvas.0 && fitted.scaled_height <= canvas.1);
+            }
+        }
+
+        // Would ask for a 16384000x1000 buffer if the whole background were scaled
+        let background = DynamicImage::ImageRgb8(RgbImage::from_fn(16384, 1, |x, _| if x < 8192 { RED } else { BLUE }));
+        let canvas = RgbImage::from_pixel(1000, 1000, GREEN);
+        let canvas = BackgroundCompositor::fit_image(&background, canvas, BackgroundFit::Cover);
+        assert_eq!(canvas.dimensions(), (1000, 1000));
+        assert!(is_red(canvas.get_pixel(0, 500)) && is_blue(canvas.get_pixel(999, 500)));
+    }
+
+    #[test]
+    fn fit_keeps_the_canvas_size() {
+        let background = DynamicImage::ImageRgb8(RgbImage::from_pixel(1000, 3, RED));
+        for fit in [BackgroundFit::Cover, BackgroundFit::Contain] {
+            let canvas = BackgroundCompositor::fit_image(&background, RgbImage::from_pixel(5, 7, GREEN), fit);
+            assert_eq!(canvas.dimensions(), (5, 7));
+        }
+    }
+}
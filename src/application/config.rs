@@ -0,0 +1,178 @@
+use std::thread;
+use std::time::Duration;
+use image::ImageFormat;
+use super::constants::{models::*, session_pool::*, batching::*, workers::*, input::*, fetch::*, jobs::*, webhooks::*};
+use super::options::PngSettings;
+
+/// Settings used to build the image processing pipeline
+#[derive(Debug, Clone)]
+pub struct ProcessorConfig {
+    /// Directory that holds the `<name>.onnx` model files
+    pub models_dir: String,
+    /// Models to load at startup; every model file found in `models_dir` when empty
+    pub models: Vec<String>,
+    /// Models outside the known list whose output is raw logits that need a sigmoid
+    pub sigmoid_models: Vec<String>,
+    /// Model used when a request does not select one
+    pub default_model: String,
+    /// ONNX Runtime sessions kept across all models, split evenly with at least one per model;
+    /// every session holds its own copy of its model in memory
+    pub session_pool_size: usize,
+    /// Intra-op threads given to each session
+    pub intra_op_threads: usize,
+    /// Largest number of images run together in one inference; 1 disables batching
+    pub batch_max_size: usize,
+    /// How long a batch waits for more images after the first one arrives
+    pub batch_window: Duration,
+    /// Threads in the pool that runs the CPU-heavy pipeline
+    pub worker_threads: usize,
+    /// Most images queued or running on the worker pool at once
+    pub queue_capacity: usize,
+    /// How long a request waits for a queue slot before it is rejected
+    pub queue_timeout: Duration,
+    /// PNG compression and filter for requests that do not set them
+    pub png: PngSettings,
+    /// Image formats accepted as input
+    pub input_formats: Vec<ImageFormat>,
+    pub decode_limits: DecodeLimits,
+}
+
+/// Largest images accepted for decoding; requests beyond these are rejected before the pixel
+/// buffers are allocated
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Most pixels, width times height
+    pub max_pixels: u64,
+    /// Most bytes a decoder may allocate
+    pub max_alloc: u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_width: DEFAULT_MAX_WIDTH,
+            max_height: DEFAULT_MAX_HEIGHT,
+            max_pixels: DEFAULT_MAX_PIXELS,
+            max_alloc: DEFAULT_MAX_DECODE_MEMORY_MB * BYTES_PER_MB,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Whether an image of this size stays within the dimension and pixel limits
+    pub fn allows(&self, width: u32, height: u32) -> bool {
+        width <= self.max_width && height <= self.max_height && width as u64 * height as u64 <= self.max_pixels
+    }
+}
+
+/// Settings for downloading input images from `image_url`
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// Hosts images may be fetched from, exact or `*.example.com`; any host when empty
+    pub allowed_hosts: Vec<String>,
+    /// Largest download accepted, in bytes
+    pub max_size: u64,
+    /// Limit for the whole download, redirects included
+    pub timeout: Duration,
+    pub max_redirects: usize,
+    /// Allow loopback, private, link-local and other non-public addresses
+    pub allow_private_addresses: bool,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            max_size: DEFAULT_MAX_SIZE_MB * BYTES_PER_MB,
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            allow_private_addresses: false,
+        }
+    }
+}
+
+/// Settings for asynchronous jobs
+#[derive(Debug, Clone)]
+pub struct JobConfig {
+    /// How long a finished job and its results are kept before cleanup
+    pub ttl: Duration,
+    /// Most job images submitted to the processor at once, across all jobs; the rest wait
+    /// without counting against the queue timeout
+    pub concurrency: usize,
+    /// Most jobs held in memory, counting finished jobs until they are removed
+    pub max_jobs: usize,
+    /// Most encoded result bytes held across all jobs
+    pub max_result_bytes: u64,
+    /// Most input bytes held for images that are queued or being processed
+    pub max_pending_bytes: u64,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(DEFAULT_TTL_SECS),
+            concurrency: available_cores(),
+            max_jobs: DEFAULT_MAX_JOBS,
+            max_result_bytes: DEFAULT_MAX_RESULT_MB * BYTES_PER_MB,
+            max_pending_bytes: DEFAULT_MAX_PENDING_MB * BYTES_PER_MB,
+        }
+    }
+}
+
+/// Settings for job completion callbacks
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Key for the HMAC-SHA256 signature; callbacks are refused without one
+    pub secret: Option<String>,
+    /// Hosts callbacks may be sent to, exact or `*.example.com`; any host when empty
+    pub allowed_hosts: Vec<String>,
+    /// Allow loopback, private, link-local and other non-public addresses
+    pub allow_private_addresses: bool,
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt up to `max_backoff`
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Limit for each delivery attempt
+    pub attempt_timeout: Duration,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            allowed_hosts: Vec::new(),
+            allow_private_addresses: false,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: Duration::from_millis(DEFAULT_INITIAL_BACKOFF_MS),
+            max_backoff: Duration::from_secs(DEFAULT_MAX_BACKOFF_SECS),
+            attempt_timeout: Duration::from_secs(DEFAULT_ATTEMPT_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl Default for ProcessorConfig {
+    fn default() -> Self {
+        Self {
+            models_dir: DEFAULT_MODELS_DIR.to_string(),
+            models: Vec::new(),
+            sigmoid_models: Vec::new(),
+            default_model: DEFAULT_MODEL.to_string(),
+            session_pool_size: (available_cores() / DEFAULT_INTRA_OP_THREADS).max(1),
+            intra_op_threads: DEFAULT_INTRA_OP_THREADS,
+            batch_max_size: DEFAULT_BATCH_MAX_SIZE,
+            batch_window: Duration::from_millis(DEFAULT_BATCH_WINDOW_MS),
+            worker_threads: available_cores(),
+            queue_capacity: available_cores() * DEFAULT_QUEUE_SLOTS_PER_WORKER,
+            queue_timeout: Duration::from_secs(DEFAULT_QUEUE_TIMEOUT_SECS),
+            png: PngSettings::default(),
+            input_formats: DEFAULT_INPUT_FORMATS.to_vec(),
+            decode_limits: DecodeLimits::default(),
+        }
+    }
+}
+
+fn available_cores() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
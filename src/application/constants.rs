@@ -2,13 +2,13 @@
 
 /// Image preprocessing constants
 pub mod preprocessing {
-    /// Normalization constants for RGB channels
-    pub const SCALE_R: f32 = 1.0 / (255.0 * 0.229);
-    pub const OFFSET_R: f32 = -0.485 / 0.229;
-    pub const SCALE_G: f32 = 1.0 / (255.0 * 0.224);
-    pub const OFFSET_G: f32 = -0.456 / 0.224;
-    pub const SCALE_B: f32 = 1.0 / (255.0 * 0.225);
-    pub const OFFSET_B: f32 = -0.406 / 0.225;
+    /// ImageNet normalization used by the U2Net family of models
+    pub const IMAGENET_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+    pub const IMAGENET_STD: [f32; 3] = [0.229, 0.224, 0.225];
+
+    /// Normalization used by the IS-Net family of models
+    pub const ISNET_MEAN: [f32; 3] = [0.5, 0.5, 0.5];
+    pub const ISNET_STD: [f32; 3] = [1.0, 1.0, 1.0];
 }
 
 /// Image postprocessing constants
@@ -26,6 +26,213 @@ pub mod postprocessing {
     pub const SMOOTH_ALPHA_RANGE: f32 = 0.8;
 }
 
+/// Input decoding constants
+pub mod input {
+    use image::ImageFormat;
+
+    /// Formats accepted when no allow-list is configured; GIF input uses the first frame
+    pub const DEFAULT_INPUT_FORMATS: &[ImageFormat] = &[
+        ImageFormat::Png,
+        ImageFormat::Jpeg,
+        ImageFormat::WebP,
+        ImageFormat::Bmp,
+        ImageFormat::Tiff,
+        ImageFormat::Gif,
+    ];
+
+    /// Decode limits, checked against the image header before pixels are allocated
+    pub const DEFAULT_MAX_WIDTH: u32 = 16_384;
+    pub const DEFAULT_MAX_HEIGHT: u32 = 16_384;
+    pub const DEFAULT_MAX_PIXELS: u64 = 40_000_000;
+    /// Most memory a decoder may allocate, in MiB
+    pub const DEFAULT_MAX_DECODE_MEMORY_MB: u64 = 512;
+    pub const BYTES_PER_MB: u64 = 1024 * 1024;
+
+    /// EXIF orientation values (TIFF 6.0): where row 0 and column 0 of the stored image belong
+    pub const ORIENTATION_FLIP_HORIZONTAL: u32 = 2;
+    pub const ORIENTATION_ROTATE_180: u32 = 3;
+    pub const ORIENTATION_FLIP_VERTICAL: u32 = 4;
+    pub const ORIENTATION_TRANSPOSE: u32 = 5;
+    pub const ORIENTATION_ROTATE_90: u32 = 6;
+    pub const ORIENTATION_TRANSVERSE: u32 = 7;
+    pub const ORIENTATION_ROTATE_270: u32 = 8;
+}
+
+/// Remote input image fetching constants
+pub mod fetch {
+    pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+    pub const DEFAULT_MAX_REDIRECTS: usize = 3;
+    /// Largest download accepted, in MiB
+    pub const DEFAULT_MAX_SIZE_MB: u64 = 10;
+    pub const ALLOWED_SCHEMES: &[&str] = &["http", "https"];
+    /// Allow-list entries starting with this match any subdomain
+    pub const WILDCARD_PREFIX: &str = "*.";
+}
+
+/// Asynchronous job constants
+pub mod jobs {
+    /// How long a finished job and its results are kept
+    pub const DEFAULT_TTL_SECS: u64 = 3600;
+    pub const CLEANUP_INTERVAL_SECS: u64 = 60;
+    /// Most jobs held in memory, running or finished
+    pub const DEFAULT_MAX_JOBS: usize = 1000;
+    /// Most encoded result data held across all jobs, in MiB
+    pub const DEFAULT_MAX_RESULT_MB: u64 = 1024;
+    /// Most input data held for images waiting for or in processing, in MiB
+    pub const DEFAULT_MAX_PENDING_MB: u64 = 1024;
+
+    /// Shared by jobs and their images
+    pub const STATUS_QUEUED: &str = "queued";
+    pub const STATUS_RUNNING: &str = "running";
+    pub const STATUS_COMPLETED: &str = "completed";
+    pub const STATUS_FAILED: &str = "failed";
+}
+
+/// Job completion callback constants
+pub mod webhooks {
+    pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+    pub const DEFAULT_INITIAL_BACKOFF_MS: u64 = 1000;
+    pub const DEFAULT_MAX_BACKOFF_SECS: u64 = 60;
+    pub const DEFAULT_ATTEMPT_TIMEOUT_SECS: u64 = 10;
+
+    pub const DELIVERY_PENDING: &str = "pending";
+    pub const DELIVERY_DELIVERED: &str = "delivered";
+    pub const DELIVERY_FAILED: &str = "failed";
+
+    pub const EVENT_JOB_COMPLETED: &str = "job.completed";
+    pub const EVENT_JOB_FAILED: &str = "job.failed";
+
+    pub const HEADER_EVENT: &str = "X-Webhook-Event";
+    pub const HEADER_JOB_ID: &str = "X-Webhook-Job-Id";
+    pub const HEADER_ATTEMPT: &str = "X-Webhook-Attempt";
+    pub const HEADER_TIMESTAMP: &str = "X-Webhook-Timestamp";
+    /// `sha256=<hex HMAC-SHA256 of "<timestamp>.<body>">`
+    pub const HEADER_SIGNATURE: &str = "X-Webhook-Signature";
+    pub const SIGNATURE_PREFIX: &str = "sha256=";
+    pub const CONTENT_TYPE_JSON: &str = "application/json";
+}
+
+/// Metadata carried from the input into the output
+pub mod metadata {
+    use exif::Tag;
+
+    /// EXIF tags copied when metadata is kept; orientation is deliberately left out because
+    /// the pixels are already rotated upright
+    pub const CARRIED_EXIF_TAGS: &[Tag] = &[Tag::Copyright, Tag::Artist, Tag::ImageDescription];
+
+    /// Color space signature in the ICC header; only RGB profiles describe the output pixels
+    pub const ICC_COLOR_SPACE_OFFSET: usize = 16;
+    pub const ICC_COLOR_SPACE_RGB: &[u8] = b"RGB ";
+}
+
+/// Output encoding constants
+pub mod encoding {
+    /// JPEG and lossy WebP quality, 1-100
+    pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+    /// PNG compression level names
+    pub const COMPRESSION_FAST: &str = "fast";
+    pub const COMPRESSION_DEFAULT: &str = "default";
+    pub const COMPRESSION_BEST: &str = "best";
+
+    /// PNG filter names
+    pub const FILTER_NONE: &str = "none";
+    pub const FILTER_SUB: &str = "sub";
+    pub const FILTER_UP: &str = "up";
+    pub const FILTER_AVG: &str = "avg";
+    pub const FILTER_PAETH: &str = "paeth";
+    pub const FILTER_ADAPTIVE: &str = "adaptive";
+
+    /// Palette-quantized PNG: number of colors and NeuQuant sampling factor (1 best, 30 fastest)
+    pub const PALETTE_SIZE: usize = 256;
+    pub const PALETTE_SAMPLE_FACTOR: i32 = 10;
+}
+
+/// Auto-crop constants
+pub mod crop {
+    /// Pixels with alpha above this, on the 0-255 scale, belong to the subject
+    pub const DEFAULT_ALPHA_THRESHOLD: u8 = 10;
+    /// Accepted range for a target canvas aspect ratio (width / height)
+    pub const MIN_ASPECT_RATIO: f32 = 0.1;
+    pub const MAX_ASPECT_RATIO: f32 = 10.0;
+    /// Upper bounds for padding, so a request cannot allocate an arbitrarily large canvas
+    pub const MAX_PADDING_PIXELS: u32 = 2048;
+    pub const MAX_PADDING_PERCENT: f32 = 100.0;
+}
+
+/// Tiled high-resolution refinement constants
+pub mod high_res {
+    /// Tiles grow beyond the model input size so the longest side needs at most this many
+    pub const MAX_TILES_PER_SIDE: u32 = 4;
+    /// Fraction of a tile shared with its neighbour
+    pub const TILE_OVERLAP: f32 = 0.25;
+
+    /// Coarse alpha values strictly between these bounds mark the subject boundary
+    pub const BOUNDARY_ALPHA_MIN: f32 = 0.02;
+    pub const BOUNDARY_ALPHA_MAX: f32 = 0.98;
+    /// Tile values are used within tile_size / BAND_RADIUS_DIVISOR pixels of the boundary
+    pub const BAND_RADIUS_DIVISOR: u32 = 16;
+}
+
+/// Guided filter constants
+pub mod guided_filter {
+    /// Rec. 601 luma weights for the grayscale guide
+    pub const LUMA_R: f32 = 0.299;
+    pub const LUMA_G: f32 = 0.587;
+    pub const LUMA_B: f32 = 0.114;
+
+    /// Window radius in units of the mask upsampling factor
+    pub const UPSAMPLING_RADIUS_SCALE: f32 = 2.0;
+    pub const MIN_RADIUS: usize = 2;
+    /// Regularization; smaller values follow guide edges more closely
+    pub const UPSAMPLING_EPSILON: f32 = 1e-3;
+    /// Coefficients are solved on a grid subsampled so the window radius is about this many cells
+    pub const SUBSAMPLE_RADIUS: usize = 4;
+}
+
+/// Alpha matting constants
+pub mod alpha_matting {
+    /// Defaults matching rembg's alpha matting parameters
+    pub const DEFAULT_FOREGROUND_THRESHOLD: u8 = 240;
+    pub const DEFAULT_BACKGROUND_THRESHOLD: u8 = 10;
+    pub const DEFAULT_ERODE_SIZE: u32 = 10;
+
+    /// Trimap values
+    pub const TRIMAP_FOREGROUND: u8 = 255;
+    pub const TRIMAP_BACKGROUND: u8 = 0;
+    pub const TRIMAP_UNKNOWN: u8 = 128;
+
+    /// Window radii tried, smallest first, when estimating local foreground/background colors
+    pub const SAMPLE_RADII: &[usize] = &[8, 32, 128];
+    /// Fraction of a window that must be known foreground (or background) to use its mean color
+    pub const MIN_SAMPLE_WEIGHT: f32 = 0.01;
+    pub const PROJECTION_EPSILON: f32 = 1e-4;
+
+    /// Guided filter applied to the solved alpha
+    pub const SMOOTHING_RADIUS: usize = 4;
+    pub const SMOOTHING_EPSILON: f32 = 1e-4;
+}
+
+/// Foreground color estimation constants
+pub mod foreground_estimation {
+    /// Blur radii of the successive blur fusion passes
+    pub const BLUR_RADII: &[usize] = &[90, 6];
+    pub const FUSION_EPSILON: f32 = 1e-5;
+}
+
+/// Background compositing constants
+pub mod compositing {
+    /// Used around a `contain` fitted background image when no color or gradient is given
+    pub const DEFAULT_FILL_COLOR: [u8; 3] = [255, 255, 255];
+
+    /// Box filter passes approximating the background Gaussian blur
+    pub const BLUR_BOX_PASSES: usize = 3;
+    /// Blurred background weight below which the original pixel is kept
+    pub const BLUR_MIN_WEIGHT: f32 = 1e-3;
+    /// Largest accepted background blur radius; wider blurs look the same on any accepted image
+    pub const MAX_BLUR_RADIUS: f32 = 1000.0;
+}
+
 /// Laplace edge detection kernel constants
 pub mod edge_detection {
     pub const KERNEL_CENTER: f32 = 8.0;
@@ -33,11 +240,52 @@ pub mod edge_detection {
 }
 
 pub mod inference {
-    pub const ORT_NAME: &'static str = "rembg";
+    pub const ORT_NAME: &str = "rembg";
+
+    /// Expected NCHW input layout
+    pub const INPUT_RANK: usize = 4;
+    pub const INPUT_CHANNELS: i64 = 3;
+    /// Value ONNX Runtime reports for dynamic axes
+    pub const DYNAMIC_DIM: i64 = -1;
 }
 
-pub mod image_processor {
-    pub const INFERENCE_PIXEL_SIZE: u32 = 320;
-    
-    pub const SILUETA_MODEL_PATH: &'static str = "models/silueta.onnx";
+/// Session pool constants
+pub mod session_pool {
+    /// Intra-op threads per session; the default session budget is the core count divided by this
+    pub const DEFAULT_INTRA_OP_THREADS: usize = 2;
+}
+
+/// Inference micro-batching constants
+pub mod batching {
+    pub const DEFAULT_BATCH_MAX_SIZE: usize = 8;
+    pub const DEFAULT_BATCH_WINDOW_MS: u64 = 5;
+}
+
+/// Worker pool constants
+pub mod workers {
+    pub const DEFAULT_QUEUE_SLOTS_PER_WORKER: usize = 4;
+    pub const DEFAULT_QUEUE_TIMEOUT_SECS: u64 = 30;
+}
+
+/// Model registry constants
+pub mod models {
+    pub const U2NET: &str = "u2net";
+    pub const U2NETP: &str = "u2netp";
+    pub const U2NET_HUMAN_SEG: &str = "u2net_human_seg";
+    pub const SILUETA: &str = "silueta";
+    pub const ISNET_GENERAL_USE: &str = "isnet-general-use";
+    pub const BIREFNET_GENERAL: &str = "birefnet-general";
+    pub const BIREFNET_GENERAL_LITE: &str = "birefnet-general-lite";
+    pub const BIREFNET_PORTRAIT: &str = "birefnet-portrait";
+
+    /// Input sizes used when a model declares dynamic spatial axes
+    pub const U2NET_PIXEL_SIZE: u32 = 320;
+    pub const ISNET_PIXEL_SIZE: u32 = 1024;
+    pub const BIREFNET_PIXEL_SIZE: u32 = 1024;
+    /// Input size for unknown models with dynamic spatial axes
+    pub const DEFAULT_PIXEL_SIZE: u32 = 1024;
+
+    pub const MODEL_FILE_EXTENSION: &str = "onnx";
+    pub const DEFAULT_MODELS_DIR: &str = "models";
+    pub const DEFAULT_MODEL: &str = SILUETA;
 }
\ No newline at end of file
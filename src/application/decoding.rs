@@ -0,0 +1,247 @@
+use std::io::Cursor;
+use image::io::{Limits, Reader as ImageReader};
+use image::{ColorType, DynamicImage, ImageError, ImageFormat, RgbImage};
+use crate::domain::AppError;
+use super::color_management::ColorConverter;
+use super::config::DecodeLimits;
+use super::constants::input::*;
+
+/// Decodes uploaded images. The format is detected from the data and checked against the
+/// allow-list, and the header dimensions are checked against the limits before decoding.
+/// The EXIF orientation is applied so the pixels are upright, as viewers display them, and CMYK
+/// JPEGs are converted to sRGB.
+pub struct InputDecoder {
+    formats: Vec<ImageFormat>,
+    limits: DecodeLimits,
+}
+
+impl InputDecoder {
+    pub fn new(formats: Vec<ImageFormat>, limits: DecodeLimits) -> Self {
+        Self { formats, limits }
+    }
+
+    pub fn formats(&self) -> &[ImageFormat] {
+        &self.formats
+    }
+
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    pub fn accepts(&self, format: ImageFormat) -> bool {
+        self.formats.contains(&format)
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<DynamicImage, AppError> {
+        let format = image::guess_format(data)
+            .map_err(|_| AppError::UnsupportedMediaType("Unrecognized image format".to_string()))?;
+        if !self.accepts(format) {
+            return Err(AppError::UnsupportedMediaType(format!("Unsupported image format: {}", format.to_mime_type())));
+        }
+
+        let (width, height) = ImageReader::with_format(Cursor::new(data), format)
+            .into_dimensions()
+            .map_err(Self::decode_error)?;
+        self.check_dimensions(width, height)?;
+
+        let img = match format {
+            ImageFormat::Jpeg => self.decode_cmyk_jpeg(data)?,
+            _ => None,
+        };
+        let img = match img {
+            Some(img) => img,
+            None => {
+                let mut reader = ImageReader::with_format(Cursor::new(data), format);
+                reader.limits(self.image_limits());
+                reader.decode().map_err(Self::decode_error)?
+            }
+        };
+
+        Ok(Self::orient(img, Self::orientation(data)))
+    }
+
+    /// Turns the decoded pixels upright; a missing or unknown orientation leaves them as they are
+    fn orient(img: DynamicImage, orientation: Option<u32>) -> DynamicImage {
+        match orientation {
+            Some(ORIENTATION_FLIP_HORIZONTAL) => img.fliph(),
+            Some(ORIENTATION_ROTATE_180) => img.rotate180(),
+            Some(ORIENTATION_FLIP_VERTICAL) => img.flipv(),
+            Some(ORIENTATION_TRANSPOSE) => img.rotate90().fliph(),
+            Some(ORIENTATION_ROTATE_90) => img.rotate90(),
+            Some(ORIENTATION_TRANSVERSE) => img.rotate270().fliph(),
+            Some(ORIENTATION_ROTATE_270) => img.rotate270(),
+            _ => img,
+        }
+    }
+
+    /// Decodes CMYK JPEGs that embed a CMYK profile, converting through the profile. `None` for
+    /// other JPEGs, which the generic decoder handles (CMYK without a profile is converted naively).
+    fn decode_cmyk_jpeg(&self, data: &[u8]) -> Result<Option<DynamicImage>, AppError> {
+        let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(data));
+        decoder.read_info().map_err(|e| AppError::ImageProcessingError(e.to_string()))?;
+        let Some(info) = decoder.info().filter(|info| info.pixel_format == jpeg_decoder::PixelFormat::CMYK32) else {
+            return Ok(None);
+        };
+        let Some(transform) = decoder.icc_profile().and_then(|profile| ColorConverter::cmyk_transform(&profile)) else {
+            return Ok(None);
+        };
+
+        decoder.set_max_decoding_buffer_size(usize::try_from(self.limits.max_alloc).unwrap_or(usize::MAX));
+        let cmyk = decoder.decode().map_err(|e| AppError::ImageProcessingError(e.to_string()))?;
+        let mut rgb = RgbImage::new(info.width as u32, info.height as u32);
+        transform.convert(&cmyk, &mut rgb);
+        Ok(Some(DynamicImage::ImageRgb8(rgb)))
+    }
+
+    /// Orientation tag of the primary image, when the container carries EXIF data
+    fn orientation(data: &[u8]) -> Option<u32> {
+        let exif = exif::Reader::new().read_from_container(&mut Cursor::new(data)).ok()?;
+        exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?.value.get_uint(0)
+    }
+
+    fn check_dimensions(&self, width: u32, height: u32) -> Result<(), AppError> {
+        if !self.limits.allows(width, height) {
+            return Err(AppError::ImageTooLarge(format!(
+                "Image of {}x{} exceeds the limits of {}x{} and {} pixels",
+                width, height, self.limits.max_width, self.limits.max_height, self.limits.max_pixels
+            )));
+        }
+        Ok(())
+    }
+
+    fn image_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.limits.max_width);
+        limits.max_image_height = Some(self.limits.max_height);
+        limits.max_alloc = Some(self.limits.max_alloc);
+        limits
+    }
+
+    fn decode_error(error: ImageError) -> AppError {
+        match error {
+            ImageError::Limits(e) => AppError::ImageTooLarge(e.to_string()),
+            e => AppError::ImageProcessingError(e.to_string()),
+        }
+    }
+}
+
+/// Whether decoded pixels have more than 8 bits per channel
+pub fn is_high_bit_depth(color: ColorType) -> bool {
+    color.bytes_per_pixel() / color.channel_count() > 1
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{GrayImage, ImageEncoder, Luma};
+    use img_parts::{jpeg::Jpeg, ImageEXIF};
+    use super::*;
+
+    /// Stored pixel shown at `(x, y)` once an image of `width`x`height` is displayed with the
+    /// orientation, following the EXIF definitions
+    fn stored_position(orientation: u32, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        match orientation {
+            1 => (x, y),
+            2 => (width - 1 - x, y),
+            3 => (width - 1 - x, height - 1 - y),
+            4 => (x, height - 1 - y),
+            5 => (y, x),
+            6 => (y, height - 1 - x),
+            7 => (width - 1 - y, height - 1 - x),
+            8 => (width - 1 - y, x),
+            _ => unreachable!(),
+        }
+    }
+
+    fn displayed_size(orientation: u32, width: u32, height: u32) -> (u32, u32) {
+        if orientation >= 5 { (height, width) } else { (width, height) }
+    }
+
+    #[test]
+    fn orients_all_exif_values() {
+        let (width, height) = (3, 2);
+        let stored = GrayImage::from_fn(width, height, |x, y| Luma([(y * width + x) as u8]));
+
+        for orientation in 1..=8 {
+            let image = DynamicImage::ImageLuma8(stored.clone());
+            let oriented = InputDecoder::orient(image, Some(orientation)).to_luma8();
+            let size = displayed_size(orientation, width, height);
+            assert_eq!(oriented.dimensions(), size, "orientation {}", orientation);
+            for (x, y, pixel) in oriented.enumerate_pixels() {
+                let (stored_x, stored_y) = stored_position(orientation, x, y, width, height);
+                let expected = stored.get_pixel(stored_x, stored_y);
+                assert_eq!(pixel, expected, "orientation {} at ({}, {})", orientation, x, y);
+            }
+        }
+
+        for orientation in [None, Some(0), Some(9)] {
+            let unchanged = InputDecoder::orient(DynamicImage::ImageLuma8(stored.clone()), orientation);
+            assert_eq!(unchanged.to_luma8(), stored);
+        }
+    }
+
+    /// 24x16 gray JPEG of six 8x8 blocks with distinct levels, tagged with an orientation
+    fn tagged_jpeg(orientation: u16) -> (GrayImage, Vec<u8>) {
+        let stored = GrayImage::from_fn(24, 16, |x, y| Luma([((y / 8 * 3 + x / 8) * 50) as u8]));
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, 95)
+            .write_image(stored.as_raw(), 24, 16, ColorType::L8)
+            .unwrap();
+
+        let field = exif::Field {
+            tag: exif::Tag::Orientation,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Short(vec![orientation]),
+        };
+        let mut writer = exif::experimental::Writer::new();
+        writer.push_field(&field);
+        let mut exif = Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+
+        let mut jpeg = Jpeg::from_bytes(encoded.into()).unwrap();
+        jpeg.set_exif(Some(exif.into_inner().into()));
+        (stored, jpeg.encoder().bytes().to_vec())
+    }
+
+    #[test]
+    fn decodes_tagged_jpeg_upright() {
+        let decoder = InputDecoder::new(vec![ImageFormat::Jpeg], DecodeLimits::default());
+        for orientation in [1, 3, 6, 8] {
+            let (stored, data) = tagged_jpeg(orientation);
+            assert_eq!(InputDecoder::orientation(&data), Some(orientation as u32));
+
+            let decoded = decoder.decode(&data).unwrap().to_luma8();
+            let (width, height) = stored.dimensions();
+            assert_eq!(decoded.dimensions(), displayed_size(orientation as u32, width, height));
+
+            // Block centers keep their level through JPEG compression
+            for (x, y, pixel) in decoded.enumerate_pixels().filter(|(x, y, _)| x % 8 == 4 && y % 8 == 4) {
+                let (stored_x, stored_y) = stored_position(orientation as u32, x, y, width, height);
+                let expected = stored.get_pixel(stored_x, stored_y)[0];
+                assert!(pixel[0].abs_diff(expected) <= 8, "orientation {} at ({}, {})", orientation, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_rotated_fixtures_upright() {
+        let decoder = InputDecoder::new(vec![ImageFormat::Jpeg], DecodeLimits::default());
+        let read = |name: &str| std::fs::read(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+        let upright = decoder.decode(&read("sample.jpg")).unwrap().to_rgb8();
+
+        // sample.jpg stored rotated and tagged so viewers show it the right way up
+        for orientation in [3, 6, 8] {
+            let data = read(&format!("sample_orientation_{}.jpg", orientation));
+            assert_eq!(InputDecoder::orientation(&data), Some(orientation));
+
+            let decoded = decoder.decode(&data).unwrap().to_rgb8();
+            assert_eq!(decoded.dimensions(), upright.dimensions(), "orientation {}", orientation);
+            let difference: u64 = decoded.as_raw().iter()
+                .zip(upright.as_raw())
+                .map(|(a, b)| u64::from(a.abs_diff(*b)))
+                .sum();
+            let mean = difference as f64 / upright.as_raw().len() as f64;
+            assert!(mean < 4.0, "orientation {}: mean difference {}", orientation, mean);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use image::{DynamicImage, ImageBuffer, Rgb, RgbImage};
+use rayon::prelude::*;
+use super::constants::foreground_estimation::*;
+use super::decoding::is_high_bit_depth;
+use super::guided_filter::box_filter;
+
+/// Foreground color estimation by blur fusion (Forte & Pitié, 2021). Semi-transparent pixels
+/// get the color of the foreground alone, removing the old background bleeding into edges.
+pub struct ForegroundEstimator;
+
+impl ForegroundEstimator {
+    /// Returns the image with colors replaced by the estimated foreground, at the bit depth of
+    /// the input. Opaque pixels keep their original color.
+    pub fn estimate(img: &DynamicImage, alpha: &[f32]) -> DynamicImage {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let high_bit_depth = is_high_bit_depth(img.color());
+        let image: [Vec<f32>; 3] = if high_bit_depth {
+            let rgb = img.to_rgb16();
+            std::array::from_fn(|c| {
+                rgb.as_raw().par_chunks_exact(3).map(|pixel| pixel[c] as f32 / u16::MAX as f32).collect()
+            })
+        } else {
+            let rgb = img.to_rgb8();
+            std::array::from_fn(|c| {
+                rgb.as_raw().par_chunks_exact(3).map(|pixel| pixel[c] as f32 / 255.0).collect()
+            })
+        };
+
+        // A coarse pass spreads colors far into the transparent band, a fine pass restores detail
+        let mut foreground = image.clone();
+        let mut background = image.clone();
+        for &radius in BLUR_RADII {
+            (foreground, background) = Self::blur_fusion(&image, &foreground, &background, alpha, width, height, radius);
+        }
+
+        if high_bit_depth {
+            let mut output = ImageBuffer::<Rgb<u16>, _>::new(width as u32, height as u32);
+            output.par_chunks_exact_mut(3).enumerate().for_each(|(i, pixel)| {
+                for c in 0..3 {
+                    pixel[c] = (foreground[c][i] * u16::MAX as f32).round() as u16;
+                }
+            });
+            return DynamicImage::ImageRgb16(output);
+        }
+
+        let mut output = RgbImage::new(width as u32, height as u32);
+        output.par_chunks_exact_mut(3).enumerate().for_each(|(i, pixel)| {
+            for c in 0..3 {
+                pixel[c] = (foreground[c][i] * 255.0).round() as u8;
+            }
+        });
+        DynamicImage::ImageRgb8(output)
+    }
+
+    fn blur_fusion(
+        image: &[Vec<f32>; 3],
+        foreground: &[Vec<f32>; 3],
+        background: &[Vec<f32>; 3],
+        alpha: &[f32],
+        width: usize,
+        height: usize,
+        radius: usize,
+    ) -> ([Vec<f32>; 3], [Vec<f32>; 3]) {
+        let blurred_alpha = box_filter(alpha, width, height, radius);
+
+        let blurred_foreground: [Vec<f32>; 3] = std::array::from_fn(|c| {
+            let weighted: Vec<f32> = foreground[c].par_iter().zip(alpha).map(|(f, a)| f * a).collect();
+            box_filter(&weighted, width, height, radius)
+                .par_iter()
+                .zip(&blurred_alpha)
+                .map(|(fa, ba)| fa / (ba + FUSION_EPSILON))
+                .collect()
+        });
+        let blurred_background: [Vec<f32>; 3] = std::array::from_fn(|c| {
+            let weighted: Vec<f32> = background[c].par_iter().zip(alpha).map(|(b, a)| b * (1.0 - a)).collect();
+            box_filter(&weighted, width, height, radius)
+                .par_iter()
+                .zip(&blurred_alpha)
+                .map(|(ba1, ba)| ba1 / ((1.0 - ba) + FUSION_EPSILON))
+                .collect()
+        });
+
+        let estimated: [Vec<f32>; 3] = std::array::from_fn(|c| {
+            (0..alpha.len())
+                .into_par_iter()
+                .map(|i| {
+                    let (a, f, b) = (alpha[i], blurred_foreground[c][i], blurred_background[c][i]);
+                    (f + a * (image[c][i] - a * f - (1.0 - a) * b)).clamp(0.0, 1.0)
+                })
+                .collect()
+        });
+
+        (estimated, blurred_background)
+    }
+}
@@ -0,0 +1,233 @@
+use image::DynamicImage;
+use rayon::prelude::*;
+use super::constants::guided_filter::*;
+
+/// Fast guided filter (He & Sun, 2015). Filters a mask so its edges follow the edges of a
+/// grayscale guide image; the linear coefficients are solved on a subsampled grid and
+/// bilinearly upsampled before being applied at full resolution.
+pub struct GuidedFilter {
+    radius: usize,
+    epsilon: f32,
+}
+
+impl GuidedFilter {
+    pub fn new(radius: usize, epsilon: f32) -> Self {
+        Self { radius: radius.max(1), epsilon }
+    }
+
+    /// Radius suited to masks upsampled from a `mask_size` model output, so the filter window
+    /// spans the blur introduced by the upsampling.
+    pub fn for_upsampled_mask(width: u32, height: u32, mask_size: u32) -> Self {
+        let scale = width.max(height) as f32 / mask_size.max(1) as f32;
+        let radius = (scale * UPSAMPLING_RADIUS_SCALE).round() as usize;
+        Self::new(radius.max(MIN_RADIUS), UPSAMPLING_EPSILON)
+    }
+
+    /// Luma of `img` in `[0, 1]`, used as the filter guide
+    pub fn guide_from_image(img: &DynamicImage) -> Vec<f32> {
+        let rgb = img.to_rgb8();
+        rgb.as_raw()
+            .par_chunks_exact(3)
+            .map(|pixel| {
+                (LUMA_R * pixel[0] as f32 + LUMA_G * pixel[1] as f32 + LUMA_B * pixel[2] as f32) / 255.0
+            })
+            .collect()
+    }
+
+    pub fn filter(&self, guide: &[f32], input: &[f32], width: usize, height: usize) -> Vec<f32> {
+        let subsample = (self.radius / SUBSAMPLE_RADIUS).max(1);
+        let (small_width, small_height) = (width.div_ceil(subsample), height.div_ceil(subsample));
+        let small_radius = (self.radius / subsample).max(1);
+
+        let small_guide = downsample(guide, width, height, subsample);
+        let small_input = downsample(input, width, height, subsample);
+
+        let mean_i = box_filter(&small_guide, small_width, small_height, small_radius);
+        let mean_p = box_filter(&small_input, small_width, small_height, small_radius);
+        let ii: Vec<f32> = small_guide.iter().map(|i| i * i).collect();
+        let ip: Vec<f32> = small_guide.iter().zip(&small_input).map(|(i, p)| i * p).collect();
+        let corr_ii = box_filter(&ii, small_width, small_height, small_radius);
+        let corr_ip = box_filter(&ip, small_width, small_height, small_radius);
+
+        let (a, b): (Vec<f32>, Vec<f32>) = (0..mean_i.len())
+            .into_par_iter()
+            .map(|k| {
+                let var_i = corr_ii[k] - mean_i[k] * mean_i[k];
+                let cov_ip = corr_ip[k] - mean_i[k] * mean_p[k];
+                let a = cov_ip / (var_i + self.epsilon);
+                (a, mean_p[k] - a * mean_i[k])
+            })
+            .unzip();
+
+        let mean_a = box_filter(&a, small_width, small_height, small_radius);
+        let mean_b = box_filter(&b, small_width, small_height, small_radius);
+
+        let mut output = vec![0f32; width * height];
+        output.par_chunks_exact_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, value) in row.iter_mut().enumerate() {
+                let a = sample_bilinear(&mean_a, small_width, small_height, x, y, subsample);
+                let b = sample_bilinear(&mean_b, small_width, small_height, x, y, subsample);
+                *value = (a * guide[y * width + x] + b).clamp(0.0, 1.0);
+            }
+        });
+        output
+    }
+}
+
+/// Mean over `(2r + 1)^2` windows clipped to the image, using a summed-area table
+pub fn box_filter(src: &[f32], width: usize, height: usize, radius: usize) -> Vec<f32> {
+    let stride = width + 1;
+    let mut table = vec![0f64; stride * (height + 1)];
+    for y in 0..height {
+        let mut row_sum = 0f64;
+        for x in 0..width {
+            row_sum += src[y * width + x] as f64;
+            table[(y + 1) * stride + x + 1] = table[y * stride + x + 1] + row_sum;
+        }
+    }
+
+    let mut output = vec![0f32; width * height];
+    output.par_chunks_exact_mut(width).enumerate().for_each(|(y, row)| {
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+        for (x, value) in row.iter_mut().enumerate() {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            let sum = table[y1 * stride + x1] - table[y0 * stride + x1] - table[y1 * stride + x0] + table[y0 * stride + x0];
+            *value = (sum / ((x1 - x0) * (y1 - y0)) as f64) as f32;
+        }
+    });
+    output
+}
+
+/// Averages `factor x factor` blocks
+fn downsample(src: &[f32], width: usize, height: usize, factor: usize) -> Vec<f32> {
+    if factor == 1 {
+        return src.to_vec();
+    }
+
+    let (small_width, small_height) = (width.div_ceil(factor), height.div_ceil(factor));
+    let mut output = vec![0f32; small_width * small_height];
+    output.par_chunks_exact_mut(small_width).enumerate().for_each(|(sy, row)| {
+        let (y0, y1) = (sy * factor, ((sy + 1) * factor).min(height));
+        for (sx, value) in row.iter_mut().enumerate() {
+            let (x0, x1) = (sx * factor, ((sx + 1) * factor).min(width));
+            let sum: f32 = (y0..y1).flat_map(|y| &src[y * width + x0..y * width + x1]).sum();
+            *value = sum / ((x1 - x0) * (y1 - y0)) as f32;
+        }
+    });
+    output
+}
+
+/// Samples a subsampled grid at full-resolution pixel `(x, y)`
+fn sample_bilinear(src: &[f32], width: usize, height: usize, x: usize, y: usize, factor: usize) -> f32 {
+    if factor == 1 {
+        return src[y * width + x];
+    }
+
+    // Block centers sit at (k + 0.5) * factor in full-resolution coordinates
+    let fx = ((x as f32 + 0.5) / factor as f32 - 0.5).clamp(0.0, (width - 1) as f32);
+    let fy = ((y as f32 + 0.5) / factor as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (dx, dy) = (fx - x0 as f32, fy - y0 as f32);
+
+    src[y0 * width + x0] * (1.0 - dx) * (1.0 - dy) +
+    src[y0 * width + x1] * dx * (1.0 - dy) +
+    src[y1 * width + x0] * (1.0 - dx) * dy +
+    src[y1 * width + x1] * dx * dy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &[&str] = &["sample.jpg", "sample1.jpg", "sample2.jpg", "sample3.jpg", "sample4.jpg"];
+
+    fn load_sample(name: &str) -> DynamicImage {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(name);
+        image::open(path).unwrap()
+    }
+
+    /// `mask` shrunk by `factor` and bilinearly enlarged again, like a mask upsampled from a model output
+    fn blurred(mask: &[f32], width: usize, height: usize, factor: usize) -> Vec<f32> {
+        let small = downsample(mask, width, height, factor);
+        let (small_width, small_height) = (width.div_ceil(factor), height.div_ceil(factor));
+        (0..width * height)
+            .map(|i| sample_bilinear(&small, small_width, small_height, i % width, i / width, factor))
+            .collect()
+    }
+
+    #[test]
+    fn constant_mask_stays_constant() {
+        let (width, height) = (40, 30);
+        let guide: Vec<f32> = (0..width * height).map(|i| ((i * 7919) % 101) as f32 / 100.0).collect();
+        let output = GuidedFilter::new(8, 1e-3).filter(&guide, &vec![0.7; width * height], width, height);
+        assert!(output.iter().all(|value| (value - 0.7).abs() < 1e-4));
+    }
+
+    #[test]
+    fn blurry_edge_snaps_to_guide_edge() {
+        let (width, height) = (64, 16);
+        // Step at x = 32 in the guide, a ramp from x = 24 to 40 in the mask
+        let guide: Vec<f32> = (0..width * height).map(|i| if i % width < 32 { 0.0 } else { 1.0 }).collect();
+        let mask: Vec<f32> = (0..width * height).map(|i| ((i % width) as f32 - 24.0).clamp(0.0, 16.0) / 16.0).collect();
+        let output = GuidedFilter::new(8, 1e-4).filter(&guide, &mask, width, height);
+
+        for y in 0..height {
+            let row = &output[y * width..(y + 1) * width];
+            // The ramp rose 1/16 per pixel; the output jumps at the guide edge instead
+            assert!(row[32] - row[31] > 0.4, "row {}: {:?}", y, row);
+            assert!(row[28..32].iter().all(|&value| value < 0.25), "row {}: {:?}", y, row);
+            assert!(row[32..36].iter().all(|&value| value > 0.7), "row {}: {:?}", y, row);
+        }
+    }
+
+    #[test]
+    fn box_filter_clips_windows_at_borders() {
+        let src: Vec<f32> = (1..=9).map(|value| value as f32).collect();
+        let output = box_filter(&src, 3, 3, 1);
+        // Corners average 2x2 windows, edges 2x3 and the center the whole image
+        assert_eq!(output, [3.0, 3.5, 4.0, 4.5, 5.0, 5.5, 6.0, 6.5, 7.0]);
+        assert_eq!(box_filter(&src, 3, 3, 10), [5.0; 9]);
+        assert_eq!(box_filter(&src, 9, 1, 0), src);
+    }
+
+    #[test]
+    fn downsample_averages_partial_blocks() {
+        // 5x3 image, so the last column and row of blocks are partial
+        let src: Vec<f32> = (0..15).map(|value| value as f32).collect();
+        let output = downsample(&src, 5, 3, 2);
+        assert_eq!(output, [3.0, 5.0, 6.5, 10.5, 12.5, 14.0]);
+        assert_eq!(downsample(&src, 5, 3, 1), src);
+    }
+
+    #[test]
+    fn sample_bilinear_interpolates_between_block_centers() {
+        let grid = [0.0, 1.0, 2.0, 3.0];
+        // 2x2 grid of 4x4 blocks, whose centers sit at 2 and 6; pixel centers are at x + 0.5
+        assert_eq!(sample_bilinear(&grid, 2, 2, 0, 0, 4), 0.0);
+        assert_eq!(sample_bilinear(&grid, 2, 2, 7, 7, 4), 3.0);
+        assert_eq!(sample_bilinear(&grid, 2, 2, 3, 0, 4), 0.375);
+        assert_eq!(sample_bilinear(&grid, 2, 2, 4, 0, 4), 0.625);
+        assert_eq!(sample_bilinear(&grid, 2, 2, 0, 4, 4), 1.25);
+        assert_eq!(sample_bilinear(&grid, 2, 2, 1, 0, 1), 1.0);
+    }
+
+    #[test]
+    fn sample_images_keep_size_and_range() {
+        for name in SAMPLES {
+            let img = load_sample(name);
+            let (width, height) = (img.width() as usize, img.height() as usize);
+            let guide = GuidedFilter::guide_from_image(&img);
+            assert_eq!(guide.len(), width * height);
+
+            // Thresholded luma, blurred as if upsampled from a 320 pixel model output
+            let sharp: Vec<f32> = guide.iter().map(|&luma| if luma > 0.5 { 1.0 } else { 0.0 }).collect();
+            let mask = blurred(&sharp, width, height, width.max(height).div_ceil(320).max(2));
+
+            let filter = GuidedFilter::for_upsampled_mask(img.width(), img.height(), 320);
+            let output = filter.filter(&guide, &mask, width, height);
+            assert_eq!(output.len(), width * height, "{}", name);
+            assert!(output.iter().all(|value| (0.0..=1.0).contains(value)), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::Arc;
+use bytes::Bytes;
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use crate::domain::AppError;
+use super::config::FetchConfig;
+use super::url_policy::{request_failure, PublicResolver, RequestFailure, UrlPolicy};
+
+/// Downloads input images from `image_url`. Every URL, including redirect targets, must use
+/// HTTP(S) and match the host allow-list; unless private addresses are allowed, hosts that
+/// resolve to loopback, private, link-local or other non-public addresses are refused, and
+/// connections only go to the public addresses a name resolved to.
+pub struct ImageFetcher {
+    client: Client,
+    policy: Arc<UrlPolicy>,
+    max_size: u64,
+}
+
+impl ImageFetcher {
+    pub fn new(config: &FetchConfig) -> Result<Self, AppError> {
+        let policy = Arc::new(UrlPolicy::new(&config.allowed_hosts, config.allow_private_addresses));
+
+        let redirect_policy = Arc::clone(&policy);
+        let max_redirects = config.max_redirects;
+        let mut builder = Client::builder()
+            .timeout(config.timeout)
+            // A proxy would resolve names itself, bypassing the address checks
+            .no_proxy()
+            .redirect(Policy::custom(move |attempt| {
+                if attempt.previous().len() > max_redirects {
+                    return attempt.error(format!("more than {} redirects", max_redirects));
+                }
+                match redirect_policy.check(attempt.url()) {
+                    Ok(()) => attempt.follow(),
+                    Err(blocked) => attempt.error(blocked),
+                }
+            }));
+        if !config.allow_private_addresses {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        let client = builder.build().map_err(|e| AppError::ModelError(e.to_string()))?;
+        Ok(Self { client, policy, max_size: config.max_size })
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<Bytes, AppError> {
+        let url = Url::parse(url.trim())
+            .map_err(|e| AppError::ImageProcessingError(format!("Invalid image URL: {}", e)))?;
+        self.policy.check(&url).map_err(|blocked| AppError::UrlNotAllowed(blocked.0))?;
+
+        let mut response = self.client.get(url).send().await.map_err(Self::request_error)?;
+        if !response.status().is_success() {
+            return Err(AppError::FetchFailed(format!("Image URL returned {}", response.status())));
+        }
+        if response.content_length().is_some_and(|length| length > self.max_size) {
+            return Err(self.too_large());
+        }
+
+        // The declared length may be missing or wrong, so the limit is enforced while reading
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(Self::request_error)? {
+            if (data.len() + chunk.len()) as u64 > self.max_size {
+                return Err(self.too_large());
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok(Bytes::from(data))
+    }
+
+    fn too_large(&self) -> AppError {
+        AppError::ImageTooLarge(format!("Image URL exceeds the limit of {} bytes", self.max_size))
+    }
+
+    /// Blocked redirects and addresses surface as 403, everything else as a failed fetch
+    /// reporting the innermost cause
+    fn request_error(error: reqwest::Error) -> AppError {
+        match request_failure(&error) {
+            RequestFailure::Blocked(blocked) => AppError::UrlNotAllowed(blocked.0.clone()),
+            RequestFailure::Other(_) if error.is_timeout() => {
+                AppError::FetchFailed("Timed out fetching image URL".to_string())
+            }
+            RequestFailure::Other(cause) => AppError::FetchFailed(format!("Failed to fetch image URL: {}", cause)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use super::*;
+
+    const BODY: &[u8] = b"image";
+    const LARGE_SIZE: usize = 2048;
+
+    /// Minimal HTTP server on a local port answering each request with `respond(path, port)`
+    async fn serve(respond: fn(&str, u16) -> Vec<u8>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match stream.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buffer[..read]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    let _ = stream.write_all(&respond(path, port)).await;
+                });
+            }
+        });
+        port
+    }
+
+    fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n{}\r\n", status, headers).into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    fn routes(path: &str, port: u16) -> Vec<u8> {
+        let redirect = |location: String| {
+            response("302 Found", &format!("Location: {}\r\nContent-Length: 0\r\n", location), b"")
+        };
+        match path {
+            "/image" => response("200 OK", &format!("Content-Length: {}\r\n", BODY.len()), BODY),
+            "/other-host" => redirect(format!("http://localhost:{}/image", port)),
+            "/large" => response("200 OK", &format!("Content-Length: {}\r\n", LARGE_SIZE), &[0; LARGE_SIZE]),
+            // No declared length, so the limit can only be enforced while reading
+            "/chunked" => {
+                let chunk = format!("{:x}\r\n{}\r\n", LARGE_SIZE / 2, "0".repeat(LARGE_SIZE / 2));
+                response("200 OK", "Transfer-Encoding: chunked\r\n", format!("{}{}0\r\n\r\n", chunk, chunk).as_bytes())
+            }
+            _ => match path.strip_prefix("/redirect/").and_then(|count| count.parse::<u32>().ok()) {
+                Some(0) => redirect(format!("http://127.0.0.1:{}/image", port)),
+                Some(count) => redirect(format!("http://127.0.0.1:{}/redirect/{}", port, count - 1)),
+                None => response("404 Not Found", "Content-Length: 0\r\n", b""),
+            },
+        }
+    }
+
+    fn local_config() -> FetchConfig {
+        FetchConfig { allow_private_addresses: true, ..FetchConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn fetches_image() {
+        let port = serve(routes).await;
+        let fetcher = ImageFetcher::new(&local_config()).unwrap();
+        let data = fetcher.fetch(&format!("http://127.0.0.1:{}/image", port)).await.unwrap();
+        assert_eq!(&data[..], BODY);
+
+        let missing = fetcher.fetch(&format!("http://127.0.0.1:{}/missing", port)).await;
+        assert!(matches!(missing, Err(AppError::FetchFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn refuses_private_addresses() {
+        let port = serve(routes).await;
+        let fetcher = ImageFetcher::new(&FetchConfig::default()).unwrap();
+        for url in [format!("http://127.0.0.1:{}/image", port), format!("http://localhost:{}/image", port)] {
+            assert!(matches!(fetcher.fetch(&url).await, Err(AppError::UrlNotAllowed(_))), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn applies_allow_list_to_redirects() {
+        let port = serve(routes).await;
+        let config = FetchConfig { allowed_hosts: vec!["127.0.0.1".to_string()], ..local_config() };
+        let fetcher = ImageFetcher::new(&config).unwrap();
+
+        assert!(fetcher.fetch(&format!("http://127.0.0.1:{}/image", port)).await.is_ok());
+        for url in [format!("http://localhost:{}/image", port), format!("http://127.0.0.1:{}/other-host", port)] {
+            assert!(matches!(fetcher.fetch(&url).await, Err(AppError::UrlNotAllowed(_))), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_redirects() {
+        let port = serve(routes).await;
+        let fetcher = ImageFetcher::new(&FetchConfig { max_redirects: 2, ..local_config() }).unwrap();
+
+        // /redirect/1 -> /redirect/0 -> /image is two redirects, within the limit; /redirect/2 needs a third
+        let data = fetcher.fetch(&format!("http://127.0.0.1:{}/redirect/1", port)).await.unwrap();
+        assert_eq!(&data[..], BODY);
+        let too_many = fetcher.fetch(&format!("http://127.0.0.1:{}/redirect/2", port)).await;
+        assert!(matches!(too_many, Err(AppError::FetchFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn limits_size() {
+        let port = serve(routes).await;
+        let fetcher = ImageFetcher::new(&FetchConfig { max_size: LARGE_SIZE as u64 - 1, ..local_config() }).unwrap();
+        for path in ["/large", "/chunked"] {
+            let result = fetcher.fetch(&format!("http://127.0.0.1:{}{}", port, path)).await;
+            assert!(matches!(result, Err(AppError::ImageTooLarge(_))), "{}", path);
+        }
+
+        let fetcher = ImageFetcher::new(&FetchConfig { max_size: LARGE_SIZE as u64, ..local_config() }).unwrap();
+        let data = fetcher.fetch(&format!("http://127.0.0.1:{}/chunked", port)).await.unwrap();
+        assert_eq!(data.len(), LARGE_SIZE);
+    }
+}
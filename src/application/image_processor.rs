@@ -1,35 +1,259 @@
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use image::{DynamicImage, ImageFormat, RgbImage};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tokio::sync::{oneshot, Semaphore};
 use crate::domain::AppError;
-use super::{preprocessing_v2::ImagePreprocessorV2, inference_v2::ModelInferenceV2, postprocessing_v2::ImagePostprocessorV2};
-use crate::application::constants::image_processor::*;
+use super::config::ProcessorConfig;
+use super::model_registry::{ModelPipeline, ModelRegistry};
+use super::options::{ColorProfile, MaskUpsampling, OutputMode, PngSettings, ProcessingOptions};
+use super::guided_filter::GuidedFilter;
+use super::alpha_matting::AlphaMatting;
+use super::foreground_estimation::ForegroundEstimator;
+use super::compositing::BackgroundCompositor;
+use super::decoding::InputDecoder;
+use super::color_management::{ColorConverter, ImageMetadata};
+use super::tiled_refinement::TiledRefiner;
 
+/// Encoded result image with its size and where the time went
+#[derive(Debug, Clone)]
+pub struct ProcessedImage {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub timings: StageTimings,
+}
+
+/// Time spent in each stage of processing one image
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTimings {
+    /// Waiting for a queue slot and a worker thread
+    pub queue: Duration,
+    /// Decoding and color conversion
+    pub decode: Duration,
+    /// Tensor preparation and the model run on the whole image
+    pub inference: Duration,
+    /// Mask refinement (including high-res tile inferences), cropping and compositing
+    pub postprocess: Duration,
+    /// Encoding and embedding metadata
+    pub encode: Duration,
+    /// From the call until the result was ready
+    pub total: Duration,
+}
+
+/// Runs the background removal pipeline on a dedicated rayon pool so decoding, inference,
+/// postprocessing and encoding never block the async runtime. At most `queue_capacity`
+/// images are queued or running; callers wait up to `queue_timeout` for a slot.
 pub struct ImageProcessor {
-    preprocessor: ImagePreprocessorV2,
-    inference: ModelInferenceV2,
-    postprocessor: ImagePostprocessorV2,
+    registry: ModelRegistry,
+    workers: ThreadPool,
+    queue: Arc<Semaphore>,
+    queue_timeout: Duration,
+    png_defaults: PngSettings,
+    decoder: InputDecoder,
 }
 
 impl ImageProcessor {
-    pub fn new() -> Result<Self, AppError> {
+    pub fn new(config: &ProcessorConfig) -> Result<Self, AppError> {
+        let workers = ThreadPoolBuilder::new()
+            .num_threads(config.worker_threads)
+            .thread_name(|index| format!("image-worker-{}", index))
+            // Without a handler rayon aborts the process when a spawned job panics
+            .panic_handler(|payload| tracing::error!("Image worker panicked: {}", panic_message(&*payload)))
+            .build()
+            .map_err(|e| AppError::ModelError(e.to_string()))?;
+
         Ok(Self {
-            preprocessor: ImagePreprocessorV2::new(INFERENCE_PIXEL_SIZE),
-            inference: ModelInferenceV2::new(SILUETA_MODEL_PATH)?,
-            postprocessor: ImagePostprocessorV2::new(INFERENCE_PIXEL_SIZE),
+            registry: ModelRegistry::new(config)?,
+            workers,
+            queue: Arc::new(Semaphore::new(config.queue_capacity.max(1))),
+            queue_timeout: config.queue_timeout,
+            png_defaults: config.png,
+            decoder: InputDecoder::new(config.input_formats.clone(), config.decode_limits),
         })
     }
+
+    pub fn model_names(&self) -> Vec<&str> {
+        self.registry.model_names()
+    }
+
+    /// Number of images processed at the same time
+    pub fn worker_threads(&self) -> usize {
+        self.workers.current_num_threads()
+    }
+
+    pub fn input_formats(&self) -> &[ImageFormat] {
+        self.decoder.formats()
+    }
+
+    pub fn accepts_input(&self, format: ImageFormat) -> bool {
+        self.decoder.accepts(format)
+    }
     
-    pub async fn remove_background(&self, image_data: &[u8]) -> Result<Vec<u8>, AppError> {
-        let img = image::load_from_memory(image_data)
-            .map_err(|e| AppError::ImageProcessingError(e.to_string()))?;
+    pub async fn remove_background(self: &Arc<Self>, image_data: Bytes, options: ProcessingOptions) -> Result<ProcessedImage, AppError> {
+        let start = Instant::now();
+        let permit = tokio::time::timeout(self.queue_timeout, Arc::clone(&self.queue).acquire_owned())
+            .await
+            .map_err(|_| AppError::ServiceUnavailable("Processing queue is full, try again later".to_string()))?
+            .map_err(|e| AppError::ServiceUnavailable(e.to_string()))?;
+
+        let (sender, receiver) = oneshot::channel();
+        let processor = Arc::clone(self);
+        self.workers.spawn(move || {
+            let queue = start.elapsed();
+            // A panic fails this image only; the worker and the other requests carry on
+            let result = catch_unwind(AssertUnwindSafe(|| processor.process(&image_data, &options)))
+                .unwrap_or_else(|payload| {
+                    let message = panic_message(&*payload);
+                    tracing::error!("Image processing panicked: {}", message);
+                    Err(AppError::ModelError(format!("Image processing failed: {}", message)))
+                })
+                .map(|mut processed| {
+                    processed.timings.queue = queue;
+                    processed.timings.total = start.elapsed();
+                    processed
+                });
+            drop(permit);
+            let _ = sender.send(result);
+        });
+
+        receiver
+            .await
+            .map_err(|_| AppError::ModelError("Image worker stopped before finishing".to_string()))?
+    }
+
+    fn process(&self, image_data: &Bytes, options: &ProcessingOptions) -> Result<ProcessedImage, AppError> {
+        let model = self.registry.get(options.model.as_deref())?;
+        if options.needs_alpha() && !options.output_format().supports_alpha() {
+            return Err(AppError::ImageProcessingError(
+                "Output format has no alpha channel, a replacement background is required".to_string()
+            ));
+        }
 
-        let (input_tensor, orig_dims, resize_dims, start_coords) = 
-            self.preprocessor.prepare_for_inference(&img)?;
+        let stage = Instant::now();
+        let mut img = self.decoder.decode(image_data)?;
 
-        let outputs = self.inference.run(input_tensor.view())?;
+        let input_metadata = if options.color_profile != ColorProfile::Strip || options.keep_metadata {
+            ImageMetadata::read(image_data)
+        } else {
+            ImageMetadata::default()
+        };
+        if options.color_profile == ColorProfile::Srgb {
+            if let Some(icc_profile) = &input_metadata.icc_profile {
+                img = ColorConverter::to_srgb(img, icc_profile);
+            }
+        }
+        let encoding = options.encoding
+            .with_format(options.output_format())
+            .with_png_defaults(self.png_defaults)
+            .with_input_depth(img.color());
+        let output_metadata = ImageMetadata {
+            icc_profile: input_metadata.icc_profile
+                .filter(|profile| options.color_profile == ColorProfile::Keep && ImageMetadata::is_rgb_profile(profile)),
+            exif: input_metadata.exif.filter(|_| options.keep_metadata),
+        };
+        let mut timings = StageTimings { decode: stage.elapsed(), ..StageTimings::default() };
 
-        self.postprocessor.process_output(
-            outputs,
-            &img,
-            (orig_dims, resize_dims, start_coords)
-        )
+        let stage = Instant::now();
+        let (input_tensor, dimensions) = model.preprocessor.prepare_for_inference(&img)?;
+
+        let outputs = model.inference.run(input_tensor.view())?;
+        timings.inference = stage.elapsed();
+
+        let stage = Instant::now();
+        let alpha = model.postprocessor.upsample_mask(&outputs, dimensions);
+        let (output, width, height) = if options.output == OutputMode::RawMask {
+            let (width, height) = (img.width(), img.height());
+            (Output::Mask(alpha), width, height)
+        } else {
+            self.refine(model, img, alpha, options)?
+        };
+        timings.postprocess = stage.elapsed();
+
+        let stage = Instant::now();
+        let data = match output {
+            // Masks are not colors, so they carry no profile or metadata
+            Output::Mask(alpha) => model.postprocessor.process_mask(&alpha, width, height, &encoding)?,
+            Output::Cutout { alpha, colors } => {
+                output_metadata.embed(model.postprocessor.process_output(&alpha, &colors, &encoding)?)?
+            }
+            Output::Composite(composite) => {
+                output_metadata.embed(model.postprocessor.process_composite(&composite, &encoding)?)?
+            }
+        };
+        timings.encode = stage.elapsed();
+
+        Ok(ProcessedImage { data, width, height, timings })
+    }
+
+    /// Turns the upsampled model mask into the final output pixels and their size
+    fn refine(
+        &self,
+        model: &ModelPipeline,
+        mut img: DynamicImage,
+        mut alpha: Vec<f32>,
+        options: &ProcessingOptions,
+    ) -> Result<(Output, u32, u32), AppError> {
+        let (mut width, mut height) = (img.width(), img.height());
+        if options.high_res {
+            TiledRefiner::refine(model, &img, &mut alpha)?;
+        }
+        if options.upsampling == MaskUpsampling::Guided {
+            let guide = GuidedFilter::guide_from_image(&img);
+            alpha = GuidedFilter::for_upsampled_mask(width, height, model.inference.metadata().output_size)
+                .filter(&guide, &alpha, width as usize, height as usize);
+        }
+
+        let mut alpha = if options.alpha_matting {
+            AlphaMatting::apply(&img, &alpha, &options.matting)
+        } else {
+            model.postprocessor.refine_alpha(&alpha, width, height)
+        };
+        model.postprocessor.combine_input_alpha(&mut alpha, &img, options.input_alpha);
+
+        if options.crop {
+            let region = model.postprocessor.crop_region(&alpha, width, &options.cropping, self.decoder.limits())?;
+            if let Some(region) = region {
+                alpha = model.postprocessor.crop_alpha(&alpha, width, height, region);
+                img = model.postprocessor.crop_image(&img, region);
+                (width, height) = (region.width, region.height);
+            }
+        }
+
+        if options.output == OutputMode::Mask {
+            return Ok((Output::Mask(alpha), width, height));
+        }
+
+        if options.background.is_enabled() {
+            let decontaminated = options.decontaminate.then(|| ForegroundEstimator::estimate(&img, &alpha));
+            let colors = decontaminated.as_ref().unwrap_or(&img);
+            let composite = BackgroundCompositor::composite(&img, colors, &alpha, &options.background, &self.decoder)?;
+            return Ok((Output::Composite(composite), width, height));
+        }
+
+        let colors = if options.decontaminate {
+            ForegroundEstimator::estimate(&img, &alpha)
+        } else {
+            img
+        };
+        Ok((Output::Cutout { alpha, colors }, width, height))
     }
-}
\ No newline at end of file
+}
+
+/// Final pixels of an image, before encoding
+enum Output {
+    Mask(Vec<f32>),
+    Cutout { alpha: Vec<f32>, colors: DynamicImage },
+    Composite(RgbImage),
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
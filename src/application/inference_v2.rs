@@ -1,34 +1,60 @@
-use ort::{session::Session, value::Tensor};
+use std::path::Path;
+use ort::{session::Session, tensor::TensorElementType, value::{Tensor, ValueType}};
 use ndarray::ArrayView4;
 use crate::domain::AppError;
 use crate::application::constants::inference::*;
 
+/// Input/output layout of a model, read from the ONNX session metadata
+#[derive(Debug, Clone)]
+pub struct ModelMetadata {
+    pub input_name: String,
+    pub output_name: String,
+    /// Whether the batch axis is dynamic, allowing several images per run
+    pub dynamic_batch: bool,
+    /// Side of the square NCHW input tensor
+    pub input_size: u32,
+    /// Side of the square mask produced by the model
+    pub output_size: u32,
+}
+
 pub struct ModelInferenceV2 {
     session: Session,
+    metadata: ModelMetadata,
 }
 
 impl ModelInferenceV2 {
-    pub fn new(model_path: &str) -> Result<Self, AppError> {
-        ort::init()
-            .with_name(ORT_NAME)
-            .commit()
-            .map_err(|e| AppError::ModelError(e.to_string()))?;
-
+    /// Loads a model and validates its input/output layout.
+    /// `fallback_size` is used for spatial axes the model declares as dynamic.
+    pub fn new(model_path: &Path, fallback_size: u32, intra_op_threads: usize) -> Result<Self, AppError> {
         let session = Session::builder()
+            .map_err(|e| AppError::ModelError(e.to_string()))?
+            .with_intra_threads(intra_op_threads)
             .map_err(|e| AppError::ModelError(e.to_string()))?
             .commit_from_file(model_path)
             .map_err(|e| AppError::ModelError(e.to_string()))?;
 
-        Ok(Self { session })
+        let metadata = Self::read_metadata(&session, fallback_size)?;
+        tracing::debug!(
+            "Model {} expects {}x{} input ({}), produces {}x{} mask ({})",
+            model_path.display(),
+            metadata.input_size, metadata.input_size, metadata.input_name,
+            metadata.output_size, metadata.output_size, metadata.output_name,
+        );
+
+        Ok(Self { session, metadata })
+    }
+
+    pub fn metadata(&self) -> &ModelMetadata {
+        &self.metadata
     }
 
     pub fn run(&self, input: ArrayView4<f32>) -> Result<Vec<f32>, AppError> {
         let tensor = self.create_tensor(input)?;
         let outputs = self.session
-            .run(ort::inputs![tensor]?)
+            .run(ort::inputs![self.metadata.input_name.as_str() => tensor]?)
             .map_err(|e| AppError::ModelError(e.to_string()))?;
 
-        let output_tensor = outputs[0]
+        let output_tensor = outputs[self.metadata.output_name.as_str()]
             .try_extract_tensor::<f32>()
             .map_err(|e| AppError::ModelError(e.to_string()))?;
 
@@ -36,9 +62,80 @@ impl ModelInferenceV2 {
     }
 
     fn create_tensor(&self, input_array: ArrayView4<f32>) -> Result<Tensor<f32>, AppError> {
-        let shape = vec![1i64, 3, 320, 320]; // Changed back to 3 channels to match model
+        let shape: Vec<i64> = input_array.shape().iter().map(|&dim| dim as i64).collect();
         let data: Vec<f32> = input_array.as_slice().unwrap().to_vec();
         Tensor::from_array((shape, data))
             .map_err(|e| AppError::ModelError(e.to_string()))
     }
-}
\ No newline at end of file
+
+    fn read_metadata(session: &Session, fallback_size: u32) -> Result<ModelMetadata, AppError> {
+        let input = session.inputs.first()
+            .ok_or_else(|| AppError::ModelError("Model has no inputs".to_string()))?;
+        let output = session.outputs.first()
+            .ok_or_else(|| AppError::ModelError("Model has no outputs".to_string()))?;
+
+        let input_dims = Self::float_tensor_dims(&input.name, &input.input_type)?;
+        let output_dims = Self::float_tensor_dims(&output.name, &output.output_type)?;
+
+        if input_dims.len() != INPUT_RANK {
+            return Err(AppError::ModelError(format!(
+                "Input {} must be NCHW with rank {}, got shape {:?}",
+                input.name, INPUT_RANK, input_dims
+            )));
+        }
+        if input_dims[0] != DYNAMIC_DIM && input_dims[0] != 1 {
+            return Err(AppError::ModelError(format!(
+                "Input {} must accept a batch of 1, got shape {:?}",
+                input.name, input_dims
+            )));
+        }
+        if input_dims[1] != DYNAMIC_DIM && input_dims[1] != INPUT_CHANNELS {
+            return Err(AppError::ModelError(format!(
+                "Input {} must have {} channels, got shape {:?}",
+                input.name, INPUT_CHANNELS, input_dims
+            )));
+        }
+
+        let input_size = Self::square_size(&input.name, &input_dims[2..], fallback_size)?;
+
+        // Masks are either [N, 1, H, W], [N, H, W] or [H, W]; the last two axes are spatial
+        if output_dims.len() < 2 {
+            return Err(AppError::ModelError(format!(
+                "Output {} must have at least 2 dimensions, got shape {:?}",
+                output.name, output_dims
+            )));
+        }
+        let output_size = Self::square_size(&output.name, &output_dims[output_dims.len() - 2..], input_size)?;
+
+        Ok(ModelMetadata {
+            input_name: input.name.clone(),
+            output_name: output.name.clone(),
+            dynamic_batch: input_dims[0] == DYNAMIC_DIM,
+            input_size,
+            output_size,
+        })
+    }
+
+    fn float_tensor_dims(name: &str, value_type: &ValueType) -> Result<Vec<i64>, AppError> {
+        match value_type {
+            ValueType::Tensor { ty: TensorElementType::Float32, dimensions, .. } => Ok(dimensions.clone()),
+            other => Err(AppError::ModelError(format!(
+                "{} must be an f32 tensor, got {:?}",
+                name, other
+            ))),
+        }
+    }
+
+    fn square_size(name: &str, spatial_dims: &[i64], fallback_size: u32) -> Result<u32, AppError> {
+        let resolve = |dim: i64| if dim == DYNAMIC_DIM { fallback_size } else { dim as u32 };
+        let (height, width) = (resolve(spatial_dims[0]), resolve(spatial_dims[1]));
+
+        if height != width || height == 0 {
+            return Err(AppError::ModelError(format!(
+                "{} must have square spatial dimensions, got {}x{}",
+                name, width, height
+            )));
+        }
+        Ok(height)
+    }
+}
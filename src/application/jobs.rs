@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use tokio::sync::Semaphore;
+use reqwest::Url;
+use uuid::Uuid;
+use crate::domain::AppError;
+use super::config::JobConfig;
+use super::constants::jobs::*;
+use super::image_processor::ImageProcessor;
+use super::options::{OutputFormat, ProcessingOptions};
+use super::webhooks::{Callback, CallbackStatus, DeliveryState, WebhookNotifier, WebhookPayload};
+
+/// Progress of one image in a job, named like the job states
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed(String),
+}
+
+impl ImageStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageStatus::Queued => STATUS_QUEUED,
+            ImageStatus::Running => STATUS_RUNNING,
+            ImageStatus::Completed => STATUS_COMPLETED,
+            ImageStatus::Failed(_) => STATUS_FAILED,
+        }
+    }
+}
+
+/// Overall state of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    /// No image has started yet
+    Queued,
+    Running,
+    /// Every image finished and at least one succeeded
+    Completed,
+    /// Every image failed
+    Failed,
+}
+
+impl JobState {
+    pub fn name(self) -> &'static str {
+        match self {
+            JobState::Queued => STATUS_QUEUED,
+            JobState::Running => STATUS_RUNNING,
+            JobState::Completed => STATUS_COMPLETED,
+            JobState::Failed => STATUS_FAILED,
+        }
+    }
+}
+
+/// Snapshot of a job for status polling
+#[derive(Debug, Clone)]
+pub struct JobStatus {
+    pub state: JobState,
+    pub images: Vec<ImageStatus>,
+    /// Time left before a finished job is removed
+    pub expires_in: Option<Duration>,
+    pub callback: Option<CallbackStatus>,
+}
+
+/// Successful results of a finished job with their image indices
+#[derive(Debug, Clone)]
+pub struct JobOutput {
+    pub format: OutputFormat,
+    pub images: Vec<(usize, Bytes)>,
+}
+
+struct Job {
+    format: OutputFormat,
+    images: Vec<ImageStatus>,
+    results: Vec<Option<Bytes>>,
+    finished_at: Option<Instant>,
+    callback: Option<JobCallback>,
+}
+
+struct JobCallback {
+    url: Url,
+    include_images: bool,
+    status: CallbackStatus,
+}
+
+impl Job {
+    fn state(&self) -> JobState {
+        if self.finished_at.is_some() {
+            if self.images.contains(&ImageStatus::Completed) {
+                JobState::Completed
+            } else {
+                JobState::Failed
+            }
+        } else if self.images.iter().all(|status| *status == ImageStatus::Queued) {
+            JobState::Queued
+        } else {
+            JobState::Running
+        }
+    }
+
+    /// Finished jobs are kept past the TTL while their callback is still being delivered
+    fn is_expired(&self, ttl: Duration) -> bool {
+        let delivering = self.callback.as_ref().is_some_and(|callback| callback.status.state == DeliveryState::Pending);
+        !delivering && self.finished_at.is_some_and(|finished_at| finished_at.elapsed() >= ttl)
+    }
+
+    fn result_bytes(&self) -> u64 {
+        self.results.iter().flatten().map(|result| result.len() as u64).sum()
+    }
+}
+
+/// Runs batches in the background so clients poll for progress instead of holding a connection
+/// open. Jobs and their results live in memory until `ttl` after they finish, bounded by
+/// `max_jobs` and `max_result_bytes`; inputs waiting for or in processing by `max_pending_bytes`.
+pub struct JobManager {
+    processor: Arc<ImageProcessor>,
+    /// Missing when callbacks are not enabled
+    notifier: Option<Arc<WebhookNotifier>>,
+    jobs: Mutex<HashMap<String, Job>>,
+    slots: Arc<Semaphore>,
+    ttl: Duration,
+    max_jobs: usize,
+    max_result_bytes: u64,
+    /// Input bytes of images that are queued or running
+    pending_bytes: AtomicU64,
+    max_pending_bytes: u64,
+}
+
+impl JobManager {
+    pub fn new(processor: Arc<ImageProcessor>, notifier: Option<Arc<WebhookNotifier>>, config: &JobConfig) -> Self {
+        Self {
+            processor,
+            notifier,
+            jobs: Mutex::new(HashMap::new()),
+            slots: Arc::new(Semaphore::new(config.concurrency.max(1))),
+            ttl: config.ttl,
+            max_jobs: config.max_jobs,
+            max_result_bytes: config.max_result_bytes,
+            pending_bytes: AtomicU64::new(0),
+            max_pending_bytes: config.max_pending_bytes,
+        }
+    }
+
+    /// Starts processing the images and returns the job id. The callback URL is checked before
+    /// the job is created, which is refused while the job, result or pending input limits would
+    /// be exceeded.
+    pub fn submit(
+        self: &Arc<Self>,
+        images: Vec<Bytes>,
+        options: ProcessingOptions,
+        callback: Option<Callback>,
+    ) -> Result<String, AppError> {
+        let callback = callback.map(|callback| self.job_callback(callback)).transpose()?;
+        let id = Uuid::new_v4().to_string();
+        let mut jobs = self.lock();
+        if jobs.len() >= self.max_jobs {
+            return Err(AppError::ServiceUnavailable("Too many jobs, try again later".to_string()));
+        }
+        if stored_bytes(&jobs) >= self.max_result_bytes {
+            return Err(AppError::ServiceUnavailable("Job result storage is full, try again later".to_string()));
+        }
+        // Reserved under the jobs lock, so concurrent submissions cannot both pass the check
+        let input_bytes: u64 = images.iter().map(|data| data.len() as u64).sum();
+        if self.pending_bytes.load(Ordering::Acquire).saturating_add(input_bytes) > self.max_pending_bytes {
+            return Err(AppError::ServiceUnavailable("Job input queue is full, try again later".to_string()));
+        }
+        self.pending_bytes.fetch_add(input_bytes, Ordering::AcqRel);
+        jobs.insert(id.clone(), Job {
+            format: options.output_format(),
+            images: vec![ImageStatus::Queued; images.len()],
+            results: vec![None; images.len()],
+            finished_at: None,
+            callback,
+        });
+        drop(jobs);
+
+        for (index, data) in images.into_iter().enumerate() {
+            let manager = Arc::clone(self);
+            let options = options.clone();
+            let id = id.clone();
+            let pending = PendingInput { manager: Arc::clone(self), size: data.len() as u64 };
+            tokio::spawn(async move {
+                let _pending = pending;
+                // The semaphore is never closed
+                let Ok(_slot) = Arc::clone(&manager.slots).acquire_owned().await else {
+                    return;
+                };
+                manager.update(&id, index, ImageStatus::Running, None);
+                match manager.processor.remove_background(data, options).await {
+                    Ok(processed) => manager.update(&id, index, ImageStatus::Completed, Some(Bytes::from(processed.data))),
+                    Err(e) => {
+                        tracing::error!("Job {} image {} failed: {:?}", id, index, e);
+                        manager.update(&id, index, ImageStatus::Failed(e.to_string()), None);
+                    }
+                }
+            });
+        }
+        Ok(id)
+    }
+
+    pub fn status(&self, id: &str) -> Option<JobStatus> {
+        let jobs = self.lock();
+        let job = jobs.get(id)?;
+        Some(JobStatus {
+            state: job.state(),
+            images: job.images.clone(),
+            expires_in: job.finished_at.map(|finished_at| self.ttl.saturating_sub(finished_at.elapsed())),
+            callback: job.callback.as_ref().map(|callback| callback.status.clone()),
+        })
+    }
+
+    /// Results of a finished job; a running job is a conflict, as is one where every image failed
+    pub fn output(&self, id: &str) -> Result<JobOutput, AppError> {
+        let jobs = self.lock();
+        let job = jobs.get(id).ok_or_else(|| AppError::NotFound(format!("Job not found: {}", id)))?;
+        match job.state() {
+            JobState::Completed => Ok(JobOutput {
+                format: job.format,
+                images: job.results
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, result)| Some((index, result.clone()?)))
+                    .collect(),
+            }),
+            JobState::Failed => Err(AppError::Conflict("Job failed, no images were processed".to_string())),
+            JobState::Queued | JobState::Running => Err(AppError::Conflict("Job is still running".to_string())),
+        }
+    }
+
+    /// Removes finished jobs older than the TTL every `CLEANUP_INTERVAL_SECS`
+    pub fn spawn_cleanup(self: &Arc<Self>) {
+        let manager = Arc::clone(self);
+        let period = Duration::from_secs(CLEANUP_INTERVAL_SECS).min(self.ttl).max(Duration::from_secs(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                manager.remove_expired();
+            }
+        });
+    }
+
+    fn remove_expired(&self) {
+        let mut jobs = self.lock();
+        let before = jobs.len();
+        jobs.retain(|_, job| !job.is_expired(self.ttl));
+        if jobs.len() < before {
+            tracing::debug!("Removed {} expired jobs", before - jobs.len());
+        }
+    }
+
+    fn job_callback(&self, callback: Callback) -> Result<JobCallback, AppError> {
+        let notifier = self.notifier.as_ref().ok_or_else(|| {
+            AppError::ImageProcessingError("Callbacks are not enabled on this server".to_string())
+        })?;
+        let url = notifier.check(&callback.url)?;
+        Ok(JobCallback {
+            status: CallbackStatus { url: url.to_string(), state: DeliveryState::Pending, attempts: Vec::new() },
+            url,
+            include_images: callback.include_images,
+        })
+    }
+
+    /// Records an image's progress; a result that would take the stored results past
+    /// `max_result_bytes` is dropped and the image fails
+    fn update(self: &Arc<Self>, id: &str, index: usize, status: ImageStatus, result: Option<Bytes>) {
+        let mut jobs = self.lock();
+        let (status, result) = match result {
+            Some(data) if stored_bytes(&jobs) + data.len() as u64 > self.max_result_bytes => {
+                tracing::warn!("Job {} image {} dropped, result storage is full", id, index);
+                (ImageStatus::Failed("Job result storage is full".to_string()), None)
+            }
+            result => (status, result),
+        };
+        let Some(job) = jobs.get_mut(id) else {
+            return;
+        };
+        job.images[index] = status;
+        job.results[index] = result;
+        if job.images.iter().all(|status| matches!(status, ImageStatus::Completed | ImageStatus::Failed(_))) {
+            job.finished_at = Some(Instant::now());
+            tracing::info!("Job {} finished", id);
+
+            if let Some(callback) = &job.callback {
+                // The payload is built outside the lock, results may be encoded into it
+                let (state, images, results) = (job.state(), job.images.clone(), job.results.clone());
+                let include_images = callback.include_images;
+                self.spawn_delivery(id.to_string(), callback.url.clone(), move |id| {
+                    WebhookPayload::new(id, state, &images, &results, include_images)
+                });
+            }
+        }
+    }
+
+    /// Sends the callback of a finished job, recording every attempt in its delivery log
+    fn spawn_delivery(
+        self: &Arc<Self>,
+        id: String,
+        url: Url,
+        payload: impl FnOnce(&str) -> WebhookPayload + Send + 'static,
+    ) {
+        let Some(notifier) = self.notifier.clone() else {
+            return;
+        };
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let payload = payload(&id);
+            let state = notifier
+                .deliver(&url, &payload, |attempt| manager.update_callback(&id, |status| status.attempts.push(attempt)))
+                .await;
+            manager.update_callback(&id, |status| status.state = state);
+            if state == DeliveryState::Delivered {
+                tracing::info!("Delivered callback for job {}", id);
+            } else {
+                tracing::error!("Gave up delivering callback for job {}", id);
+            }
+        });
+    }
+
+    fn update_callback(&self, id: &str, update: impl FnOnce(&mut CallbackStatus)) {
+        if let Some(callback) = self.lock().get_mut(id).and_then(|job| job.callback.as_mut()) {
+            update(&mut callback.status);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Job>> {
+        // A panic while holding the lock leaves the map consistent, so poisoning is ignored
+        self.jobs.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Input of a queued or running image, released from the pending total when its task ends
+struct PendingInput {
+    manager: Arc<JobManager>,
+    size: u64,
+}
+
+impl Drop for PendingInput {
+    fn drop(&mut self) {
+        self.manager.pending_bytes.fetch_sub(self.size, Ordering::AcqRel);
+    }
+}
+
+fn stored_bytes(jobs: &HashMap<String, Job>) -> u64 {
+    jobs.values().map(Job::result_bytes).sum()
+}
@@ -1,6 +1,22 @@
 pub mod image_processor;
 pub mod constants;
+pub mod config;
+pub mod options;
+pub mod model_registry;
+pub mod image_fetcher;
+pub mod jobs;
+pub mod webhooks;
 
 mod preprocessing_v2;
 mod inference_v2;
-mod postprocessing_v2; 
\ No newline at end of file
+mod session_pool;
+mod batch_scheduler;
+mod postprocessing_v2; 
+mod tiled_refinement;
+mod guided_filter;
+mod alpha_matting;
+mod foreground_estimation;
+mod compositing;
+mod decoding;
+mod color_management;
+mod url_policy;
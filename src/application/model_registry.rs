@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::domain::AppError;
+use super::{preprocessing_v2::ImagePreprocessorV2, session_pool::SessionPool, postprocessing_v2::ImagePostprocessorV2};
+use super::batch_scheduler::BatchScheduler;
+use super::config::ProcessorConfig;
+use super::constants::{inference::*, models::*, preprocessing::*};
+
+/// Per-channel mean/std normalization applied to the input tensor
+#[derive(Debug, Clone, Copy)]
+pub struct Normalization {
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+}
+
+/// How to feed a model and read its output
+#[derive(Debug, Clone, Copy)]
+pub struct ModelSpec {
+    /// Input size used when the model's spatial axes are dynamic
+    pub input_size: u32,
+    pub normalization: Normalization,
+    /// The output is raw logits rather than probabilities
+    pub sigmoid: bool,
+}
+
+const IMAGENET: Normalization = Normalization { mean: IMAGENET_MEAN, std: IMAGENET_STD };
+const ISNET: Normalization = Normalization { mean: ISNET_MEAN, std: ISNET_STD };
+
+const U2NET_SPEC: ModelSpec = ModelSpec { input_size: U2NET_PIXEL_SIZE, normalization: IMAGENET, sigmoid: false };
+const ISNET_SPEC: ModelSpec = ModelSpec { input_size: ISNET_PIXEL_SIZE, normalization: ISNET, sigmoid: false };
+const BIREFNET_SPEC: ModelSpec = ModelSpec { input_size: BIREFNET_PIXEL_SIZE, normalization: IMAGENET, sigmoid: true };
+
+/// Models whose preprocessing is known; other model files load with [`ModelSpec::generic`]
+pub const KNOWN_MODELS: &[(&str, ModelSpec)] = &[
+    (U2NET, U2NET_SPEC),
+    (U2NETP, U2NET_SPEC),
+    (U2NET_HUMAN_SEG, U2NET_SPEC),
+    (SILUETA, U2NET_SPEC),
+    (ISNET_GENERAL_USE, ISNET_SPEC),
+    (BIREFNET_GENERAL, BIREFNET_SPEC),
+    (BIREFNET_GENERAL_LITE, BIREFNET_SPEC),
+    (BIREFNET_PORTRAIT, BIREFNET_SPEC),
+];
+
+impl ModelSpec {
+    /// Spec for a model outside the known list: ImageNet normalization, the input size from the
+    /// model metadata and a sigmoid when the configuration says it outputs logits
+    pub fn generic(sigmoid: bool) -> Self {
+        Self { input_size: DEFAULT_PIXEL_SIZE, normalization: IMAGENET, sigmoid }
+    }
+
+    pub fn for_model(name: &str, config: &ProcessorConfig) -> Self {
+        let sigmoid = config.sigmoid_models.iter().any(|model| model == name);
+        match KNOWN_MODELS.iter().find(|(known, _)| *known == name) {
+            Some((_, spec)) => ModelSpec { sigmoid: spec.sigmoid || sigmoid, ..*spec },
+            None => Self::generic(sigmoid),
+        }
+    }
+}
+
+/// Everything needed to run one model end to end
+pub struct ModelPipeline {
+    pub preprocessor: ImagePreprocessorV2,
+    pub inference: BatchScheduler,
+    pub postprocessor: ImagePostprocessorV2,
+}
+
+impl ModelPipeline {
+    fn load(
+        name: &str,
+        spec: &ModelSpec,
+        model_path: &Path,
+        sessions: usize,
+        config: &ProcessorConfig,
+    ) -> Result<Self, AppError> {
+        let pool = SessionPool::new(model_path, spec.input_size, sessions, config.intra_op_threads)?;
+        let metadata = pool.metadata();
+        tracing::info!(
+            "Model {} expects {}x{} input, produces {}x{} mask, {} sessions with {} intra-op threads each, dynamic batch: {}, sigmoid: {}",
+            name,
+            metadata.input_size, metadata.input_size,
+            metadata.output_size, metadata.output_size,
+            pool.size(), config.intra_op_threads, metadata.dynamic_batch, spec.sigmoid,
+        );
+
+        let inference = BatchScheduler::new(name, pool, config.batch_max_size, config.batch_window)?;
+        let metadata = inference.metadata();
+
+        Ok(Self {
+            preprocessor: ImagePreprocessorV2::new(metadata.input_size, spec.normalization),
+            postprocessor: ImagePostprocessorV2::new(metadata.input_size, metadata.output_size, spec.sigmoid),
+            inference,
+        })
+    }
+}
+
+pub struct ModelRegistry {
+    models: HashMap<String, ModelPipeline>,
+    default_model: String,
+}
+
+impl ModelRegistry {
+    pub fn new(config: &ProcessorConfig) -> Result<Self, AppError> {
+        ort::init()
+            .with_name(ORT_NAME)
+            .commit()
+            .map_err(|e| AppError::ModelError(e.to_string()))?;
+
+        let models_dir = Path::new(&config.models_dir);
+        let mut models = HashMap::new();
+
+        let names = Self::select_models(config, models_dir)?;
+        let sessions = (config.session_pool_size / names.len().max(1)).max(1);
+        if sessions * names.len() > config.session_pool_size {
+            tracing::warn!(
+                "Session budget of {} is below one per model, loading {} sessions for {} models",
+                config.session_pool_size, sessions * names.len(), names.len()
+            );
+        }
+
+        for name in names {
+            let model_path = models_dir.join(format!("{}.{}", name, MODEL_FILE_EXTENSION));
+            let spec = ModelSpec::for_model(&name, config);
+            if !KNOWN_MODELS.iter().any(|(known, _)| *known == name) {
+                tracing::info!("Model {} is not a known model, using generic preprocessing", name);
+            }
+
+            tracing::info!("Loading model {} from {}", name, model_path.display());
+            let pipeline = ModelPipeline::load(&name, &spec, &model_path, sessions, config).map_err(|e| {
+                AppError::ModelError(format!("Failed to load model {}: {:?}", name, e))
+            })?;
+            models.insert(name, pipeline);
+        }
+
+        if !models.contains_key(&config.default_model) {
+            return Err(AppError::ModelError(format!(
+                "Default model {} is not loaded",
+                config.default_model
+            )));
+        }
+
+        Ok(Self {
+            models,
+            default_model: config.default_model.clone(),
+        })
+    }
+
+    /// Resolves a model by name, falling back to the default model when none is requested
+    pub fn get(&self, name: Option<&str>) -> Result<&ModelPipeline, AppError> {
+        let name = name.unwrap_or(&self.default_model);
+        self.models.get(name).ok_or_else(|| {
+            AppError::ImageProcessingError(format!(
+                "Unknown model: {}. Available models: {}",
+                name,
+                self.model_names().join(", ")
+            ))
+        })
+    }
+
+    pub fn model_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.models.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the models to load: the configured list, or every model file in `models_dir`
+    /// when none is configured
+    fn select_models(config: &ProcessorConfig, models_dir: &Path) -> Result<Vec<String>, AppError> {
+        if !config.models.is_empty() {
+            return Ok(config.models.clone());
+        }
+
+        let entries = std::fs::read_dir(models_dir).map_err(|e| {
+            AppError::ModelError(format!("Failed to read models directory {}: {}", models_dir.display(), e))
+        })?;
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == MODEL_FILE_EXTENSION))
+            .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+            .collect();
+        names.sort_unstable();
+        Ok(names)
+    }
+}
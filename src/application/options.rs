@@ -0,0 +1,322 @@
+use std::str::FromStr;
+use bytes::Bytes;
+use image::ColorType;
+use super::constants::alpha_matting::*;
+use super::constants::crop::DEFAULT_ALPHA_THRESHOLD;
+use super::constants::encoding::*;
+use super::decoding::is_high_bit_depth;
+
+/// How the model mask is scaled up to the original image size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MaskUpsampling {
+    /// Plain bilinear interpolation
+    #[default]
+    Bilinear,
+    /// Bilinear interpolation followed by a guided filter using the original image as guide
+    Guided,
+}
+
+/// What the response image contains
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// The original image with the background made transparent
+    #[default]
+    Cutout,
+    /// The final alpha as a grayscale image
+    Mask,
+    /// The model mask scaled to the image size, before any refinement
+    RawMask,
+}
+
+/// How transparency already present in the input combines with the predicted mask
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputAlpha {
+    /// Multiply both, so partially transparent input stays partially transparent
+    #[default]
+    Multiply,
+    /// Keep the lower of both values
+    Min,
+    /// Use the predicted mask only
+    Ignore,
+}
+
+/// What happens to an ICC profile embedded in the input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorProfile {
+    /// Embed the input profile in the output so colors render as in the input
+    #[default]
+    Keep,
+    /// Convert the pixels to sRGB and leave the output untagged; the conversion runs at 8 bits,
+    /// so 16-bit images come out at 8 bits per channel
+    Srgb,
+    /// Drop the profile without converting
+    Strip,
+}
+
+/// Encoding of the response image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    /// Keeps the alpha channel; lossless unless `lossless` is off, then lossy at `quality`
+    WebP,
+    /// No alpha channel, so cutouts need a replacement background
+    Jpeg,
+    Tiff,
+}
+
+impl OutputFormat {
+    pub fn supports_alpha(self) -> bool {
+        self != Self::Jpeg
+    }
+
+    /// Whether 16 bits per channel can be written
+    pub fn supports_high_bit_depth(self) -> bool {
+        matches!(self, Self::Png | Self::Tiff)
+    }
+}
+
+/// PNG deflate effort
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngCompression {
+    #[default]
+    Fast,
+    Default,
+    Best,
+}
+
+impl FromStr for PngCompression {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            COMPRESSION_FAST => Ok(Self::Fast),
+            COMPRESSION_DEFAULT => Ok(Self::Default),
+            COMPRESSION_BEST => Ok(Self::Best),
+            _ => Err(()),
+        }
+    }
+}
+
+/// PNG scanline filter; `Adaptive` picks one per row
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    #[default]
+    Adaptive,
+}
+
+impl FromStr for PngFilter {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            FILTER_NONE => Ok(Self::None),
+            FILTER_SUB => Ok(Self::Sub),
+            FILTER_UP => Ok(Self::Up),
+            FILTER_AVG => Ok(Self::Avg),
+            FILTER_PAETH => Ok(Self::Paeth),
+            FILTER_ADAPTIVE => Ok(Self::Adaptive),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Server-wide PNG settings, used when a request does not choose its own
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PngSettings {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+}
+
+/// Encoder settings for the response image
+#[derive(Debug, Clone, Copy)]
+pub struct EncodingOptions {
+    pub format: OutputFormat,
+    /// Replaces `format` when it has no alpha channel and a cutout keeps its transparency. Set
+    /// for formats picked by content negotiation; an explicitly requested format has none.
+    pub alpha_fallback: Option<OutputFormat>,
+    /// JPEG and lossy WebP quality, 1-100
+    pub quality: u8,
+    /// Encode WebP losslessly; lossy WebP keeps alpha and is much smaller for photos
+    pub lossless: bool,
+    /// PNG compression and filter; `None` uses the server default
+    pub png_compression: Option<PngCompression>,
+    pub png_filter: Option<PngFilter>,
+    /// Quantize color PNGs to an 8-bit palette with transparency
+    pub png_palette: bool,
+    /// Write cutouts and masks with 16 bits per channel; follows the input, not a request option
+    pub high_bit_depth: bool,
+}
+
+impl EncodingOptions {
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Fills the PNG settings the request left unset
+    pub fn with_png_defaults(mut self, defaults: PngSettings) -> Self {
+        self.png_compression.get_or_insert(defaults.compression);
+        self.png_filter.get_or_insert(defaults.filter);
+        self
+    }
+
+    /// Keeps 16 bits per channel when the input has more than 8 and the format can store them.
+    /// Palette PNGs are always 8-bit.
+    pub fn with_input_depth(mut self, color: ColorType) -> Self {
+        self.high_bit_depth = is_high_bit_depth(color) && self.format.supports_high_bit_depth() && !self.png_palette;
+        self
+    }
+}
+
+impl Default for EncodingOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::default(),
+            alpha_fallback: None,
+            quality: DEFAULT_JPEG_QUALITY,
+            lossless: true,
+            png_compression: None,
+            png_filter: None,
+            png_palette: false,
+            high_bit_depth: false,
+        }
+    }
+}
+
+/// How a background image is scaled to the output size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackgroundFit {
+    /// Fill the whole output, cropping the image overflow
+    #[default]
+    Cover,
+    /// Show the whole image, leaving the color or gradient visible around it
+    Contain,
+}
+
+/// Linear gradient between two colors; 0 degrees runs left to right, 90 top to bottom
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gradient {
+    pub from: [u8; 3],
+    pub to: [u8; 3],
+    pub angle_degrees: f32,
+}
+
+/// Replacement background; the output is opaque when any of these is set
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundOptions {
+    pub color: Option<[u8; 3]>,
+    pub gradient: Option<Gradient>,
+    /// Encoded image, in any supported input format
+    pub image: Option<Bytes>,
+    pub fit: BackgroundFit,
+    /// Keep the original background, blurred with this Gaussian radius in pixels
+    pub blur_radius: Option<f32>,
+}
+
+impl BackgroundOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.color.is_some() || self.gradient.is_some() || self.image.is_some() || self.blur_radius.is_some()
+    }
+}
+
+/// Trimap parameters for alpha matting, on the 0-255 mask scale
+#[derive(Debug, Clone, Copy)]
+pub struct AlphaMattingOptions {
+    /// Mask values above this are certain foreground
+    pub foreground_threshold: u8,
+    /// Mask values below this are certain background
+    pub background_threshold: u8,
+    /// Size of the erosion window applied to both certain regions
+    pub erode_size: u32,
+}
+
+impl Default for AlphaMattingOptions {
+    fn default() -> Self {
+        Self {
+            foreground_threshold: DEFAULT_FOREGROUND_THRESHOLD,
+            background_threshold: DEFAULT_BACKGROUND_THRESHOLD,
+            erode_size: DEFAULT_ERODE_SIZE,
+        }
+    }
+}
+
+/// Margin kept around the subject when cropping
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CropPadding {
+    Pixels(u32),
+    /// Percentage of the longer side of the subject bounding box
+    Percent(f32),
+}
+
+impl Default for CropPadding {
+    fn default() -> Self {
+        Self::Pixels(0)
+    }
+}
+
+/// Auto-crop parameters
+#[derive(Debug, Clone, Copy)]
+pub struct CropOptions {
+    pub padding: CropPadding,
+    /// Target canvas width / height; the subject is centered and the canvas extended with
+    /// transparent pixels as needed
+    pub aspect_ratio: Option<f32>,
+    /// Pixels with alpha above this, on the 0-255 scale, define the bounding box
+    pub alpha_threshold: u8,
+}
+
+impl Default for CropOptions {
+    fn default() -> Self {
+        Self {
+            padding: CropPadding::default(),
+            aspect_ratio: None,
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD,
+        }
+    }
+}
+
+/// Per-request processing options
+#[derive(Debug, Clone, Default)]
+pub struct ProcessingOptions {
+    /// Name of the registered model to use, or the default model when `None`
+    pub model: Option<String>,
+    /// Re-run the model on tiles around the subject boundary for a sharper full-resolution mask
+    pub high_res: bool,
+    pub upsampling: MaskUpsampling,
+    /// Solve a fine alpha in the trimap unknown band instead of the default edge smoothing
+    pub alpha_matting: bool,
+    pub matting: AlphaMattingOptions,
+    /// Replace the color of semi-transparent pixels with the estimated foreground color
+    pub decontaminate: bool,
+    pub output: OutputMode,
+    pub background: BackgroundOptions,
+    /// Trim the output to the subject bounding box
+    pub crop: bool,
+    pub cropping: CropOptions,
+    pub encoding: EncodingOptions,
+    pub input_alpha: InputAlpha,
+    pub color_profile: ColorProfile,
+    /// Carry copyright, artist and description EXIF tags into the output
+    pub keep_metadata: bool,
+}
+
+impl ProcessingOptions {
+    /// Cutouts without a replacement background keep their transparency
+    pub fn needs_alpha(&self) -> bool {
+        self.output == OutputMode::Cutout && !self.background.is_enabled()
+    }
+
+    /// Format the result is encoded in, taking the alpha fallback when the output needs it
+    pub fn output_format(&self) -> OutputFormat {
+        match self.encoding.alpha_fallback {
+            Some(fallback) if self.needs_alpha() && !self.encoding.format.supports_alpha() => fallback,
+            _ => self.encoding.format,
+        }
+    }
+}
@@ -1,62 +1,78 @@
-use image::{DynamicImage, ImageEncoder};
+use std::io::Cursor;
+use color_quant::NeuQuant;
+use image::codecs::{jpeg::JpegEncoder, png::{CompressionType, FilterType, PngEncoder}, tiff::TiffEncoder, webp::WebPEncoder};
+use image::{DynamicImage, ImageBuffer, ImageEncoder, RgbImage, RgbaImage};
 use rayon::prelude::*;
+use webp::PixelLayout;
 use crate::domain::AppError;
-use crate::application::constants::{postprocessing::*, edge_detection::*};
+use crate::application::constants::{postprocessing::*, edge_detection::*, encoding::*};
+use super::config::DecodeLimits;
+use super::options::{CropOptions, CropPadding, EncodingOptions, InputAlpha, OutputFormat, PngCompression, PngFilter};
+use super::decoding::is_high_bit_depth;
+use super::preprocessing_v2::LetterboxDimensions;
+
+/// Output canvas in original image coordinates; it may extend past the image borders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRegion {
+    pub x: i64,
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+}
 
 pub struct ImagePostprocessorV2 {
     pixel_size: u32,
+    output_size: u32,
+    sigmoid: bool,
 }
 
 impl ImagePostprocessorV2 {
-    pub fn new(pixel_size: u32) -> Self {
-        Self { pixel_size }
+    /// `pixel_size` is the model input side, `output_size` the side of the mask it produces;
+    /// `sigmoid` maps raw logits to probabilities before upsampling
+    pub fn new(pixel_size: u32, output_size: u32, sigmoid: bool) -> Self {
+        Self { pixel_size, output_size, sigmoid }
     }
 
-    pub fn process_output(
-        &self,
-        outputs: Vec<f32>,
-        img: &DynamicImage,
-        dimensions: ((u32, u32), (u32, u32), (u32, u32)),
-    ) -> Result<Vec<u8>, AppError> {
+    /// First pass: maps the model mask back onto the original image with bilinear interpolation
+    pub fn upsample_mask(&self, outputs: &[f32], dimensions: LetterboxDimensions) -> Vec<f32> {
+        let activated: Vec<f32>;
+        let outputs = if self.sigmoid {
+            activated = outputs.par_iter().map(|&logit| 1.0 / (1.0 + (-logit).exp())).collect();
+            &activated
+        } else {
+            outputs
+        };
         let ((orig_width, orig_height), (resize_width, resize_height), (start_x, start_y)) = dimensions;
-        let pixel_size_usize = self.pixel_size as usize;
-
-        let mut output_buffer = Vec::with_capacity((orig_width * orig_height * 4) as usize);
-        let encoder = image::codecs::png::PngEncoder::new(&mut output_buffer);
-
-        let img_rgba = img.to_rgba8();
-        let rgba_buffer = img_rgba.as_raw();
+        let output_size_usize = self.output_size as usize;
+        let mask_scale = self.output_size as f32 / self.pixel_size as f32;
 
         let orig_width_usize = orig_width as usize;
         let total_pixels = orig_width_usize * orig_height as usize;
-        let resize_width_f32 = resize_width as f32;
-        let resize_height_f32 = resize_height as f32;
+        let resize_width_f32 = resize_width as f32 * mask_scale;
+        let resize_height_f32 = resize_height as f32 * mask_scale;
+        let (start_x_f32, start_y_f32) = (start_x as f32 * mask_scale, start_y as f32 * mask_scale);
         let x_scale = 1.0 / orig_width as f32;
         let y_scale = 1.0 / orig_height as f32;
 
-        let mut rgba_data = vec![0u8; total_pixels * 4];
-        let outputs_slice = outputs.as_slice();
-
-        // First pass: Calculate raw alpha values with bilinear interpolation
         let mut alpha_buffer = vec![0f32; total_pixels];
         alpha_buffer.par_iter_mut().enumerate().for_each(|(i, alpha)| {
             let x = i % orig_width_usize;
             let y = i / orig_width_usize;
 
             // Calculate exact mask position with floating point precision
-            let mask_x = (x as f32 * x_scale * resize_width_f32) + start_x as f32;
-            let mask_y = (y as f32 * y_scale * resize_height_f32) + start_y as f32;
+            let mask_x = (x as f32 * x_scale * resize_width_f32) + start_x_f32;
+            let mask_y = (y as f32 * y_scale * resize_height_f32) + start_y_f32;
 
             // Bilinear interpolation
             let x0 = mask_x.floor() as usize;
             let y0 = mask_y.floor() as usize;
-            let x1 = (x0 + 1).min(pixel_size_usize - 1);
-            let y1 = (y0 + 1).min(pixel_size_usize - 1);
+            let x1 = (x0 + 1).min(output_size_usize - 1);
+            let y1 = (y0 + 1).min(output_size_usize - 1);
             
             let dx = mask_x - x0 as f32;
             let dy = mask_y - y0 as f32;
 
-            let get_value = |x, y| outputs_slice.get(y * pixel_size_usize + x).copied().unwrap_or(0.0);
+            let get_value = |x, y| outputs.get(y * output_size_usize + x).copied().unwrap_or(0.0);
             
             let v00 = get_value(x0, y0);
             let v01 = get_value(x0, y1);
@@ -72,16 +88,24 @@ impl ImagePostprocessorV2 {
             *alpha = interpolated.clamp(0.0, 1.0);
         });
 
-        // Second pass: Edge detection and alpha refinement
-        rgba_data.par_chunks_exact_mut(4).enumerate().for_each(|(i, chunk)| {
-            let x = i % orig_width_usize;
-            let y = i / orig_width_usize;
+        alpha_buffer
+    }
+
+    /// Second pass: smooths the upsampled alpha, sharpening detected edges
+    pub fn refine_alpha(&self, alpha_buffer: &[f32], width: u32, height: u32) -> Vec<f32> {
+        let width_usize = width as usize;
+        let mut refined = vec![0f32; alpha_buffer.len()];
+
+        // Edge detection and alpha refinement
+        refined.par_iter_mut().enumerate().for_each(|(i, refined_alpha)| {
+            let x = i % width_usize;
+            let y = i / width_usize;
             
             // Edge detection using Laplace operator
-            let edge_score = self.calculate_edge_score(x, y, &alpha_buffer, orig_width_usize, orig_height as usize);
+            let edge_score = self.calculate_edge_score(x, y, alpha_buffer, width_usize, height as usize);
             
             let alpha = alpha_buffer[i];
-            let smoothed_alpha = if edge_score > EDGE_DETECTION_THRESHOLD {
+            *refined_alpha = if edge_score > EDGE_DETECTION_THRESHOLD {
                 // Use cubic interpolation for edges
                 let t = ((alpha - EDGE_ALPHA_MIN) / EDGE_ALPHA_RANGE).clamp(0.0, 1.0);
                 (t * t * (3.0 - 2.0 * t)) * EDGE_BLEND_FACTOR + alpha * (1.0 - EDGE_BLEND_FACTOR)
@@ -90,20 +114,298 @@ impl ImagePostprocessorV2 {
                 let t = ((alpha - SMOOTH_ALPHA_MIN) / SMOOTH_ALPHA_RANGE).clamp(0.0, 1.0);
                 t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
             };
+        });
+
+        refined
+    }
+
+    /// Canvas framing the subject: the bounding box of pixels above the alpha threshold, padded
+    /// and widened to the requested aspect ratio around its center. `None` when no pixel passes.
+    /// Padding and aspect ratio can grow the canvas far past the input, so it is held to the
+    /// decode limits before anything is allocated.
+    pub fn crop_region(
+        &self,
+        alpha_buffer: &[f32],
+        width: u32,
+        options: &CropOptions,
+        limits: &DecodeLimits,
+    ) -> Result<Option<CropRegion>, AppError> {
+        let threshold = options.alpha_threshold as f32 / 255.0;
+        let Some((min_x, min_y, max_x, max_y)) = alpha_buffer
+            .par_chunks_exact(width as usize)
+            .enumerate()
+            .filter_map(|(y, row)| {
+                let first = row.iter().position(|&a| a > threshold)?;
+                let last = row.iter().rposition(|&a| a > threshold)?;
+                Some((first, y, last, y))
+            })
+            .reduce_with(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+        else {
+            return Ok(None);
+        };
+
+        let box_width = (max_x - min_x + 1) as f32;
+        let box_height = (max_y - min_y + 1) as f32;
+        let padding = match options.padding {
+            CropPadding::Pixels(pixels) => pixels as f32,
+            CropPadding::Percent(percent) => (box_width.max(box_height) * percent / 100.0).round(),
+        };
 
+        let mut canvas_width = box_width + 2.0 * padding;
+        let mut canvas_height = box_height + 2.0 * padding;
+        if let Some(ratio) = options.aspect_ratio {
+            if canvas_width / canvas_height < ratio {
+                canvas_width = (canvas_height * ratio).round();
+            } else {
+                canvas_height = (canvas_width / ratio).round();
+            }
+        }
+
+        // Float to int casts saturate, so oversized canvases still fail the check
+        let (region_width, region_height) = (canvas_width as u32, canvas_height as u32);
+        if !limits.allows(region_width, region_height) {
+            return Err(AppError::ImageTooLarge(format!(
+                "Cropped canvas of {}x{} exceeds the limits of {}x{} and {} pixels",
+                region_width, region_height, limits.max_width, limits.max_height, limits.max_pixels
+            )));
+        }
+
+        let center_x = (min_x + max_x + 1) as f32 / 2.0;
+        let center_y = (min_y + max_y + 1) as f32 / 2.0;
+        Ok(Some(CropRegion {
+            x: (center_x - canvas_width / 2.0).round() as i64,
+            y: (center_y - canvas_height / 2.0).round() as i64,
+            width: region_width,
+            height: region_height,
+        }))
+    }
+
+    /// Cuts `region` out of the alpha; pixels outside the image are transparent
+    pub fn crop_alpha(&self, alpha_buffer: &[f32], width: u32, height: u32, region: CropRegion) -> Vec<f32> {
+        let mut cropped = vec![0f32; region.width as usize * region.height as usize];
+        cropped.par_chunks_exact_mut(region.width as usize).enumerate().for_each(|(row, out)| {
+            let y = region.y + row as i64;
+            if y < 0 || y >= height as i64 {
+                return;
+            }
+            for (column, value) in out.iter_mut().enumerate() {
+                let x = region.x + column as i64;
+                if x >= 0 && x < width as i64 {
+                    *value = alpha_buffer[y as usize * width as usize + x as usize];
+                }
+            }
+        });
+        cropped
+    }
+
+    /// Cuts `region` out of the image, keeping 16 bits per channel when the image has them;
+    /// pixels outside the image are transparent
+    pub fn crop_image(&self, img: &DynamicImage, region: CropRegion) -> DynamicImage {
+        if is_high_bit_depth(img.color()) {
+            let mut canvas = ImageBuffer::new(region.width, region.height);
+            image::imageops::replace(&mut canvas, &img.to_rgba16(), -region.x, -region.y);
+            DynamicImage::ImageRgba16(canvas)
+        } else {
+            let mut canvas = RgbaImage::new(region.width, region.height);
+            image::imageops::replace(&mut canvas, &img.to_rgba8(), -region.x, -region.y);
+            DynamicImage::ImageRgba8(canvas)
+        }
+    }
+
+    /// Combines transparency already present in the input with the predicted alpha
+    pub fn combine_input_alpha(&self, alpha_buffer: &mut [f32], img: &DynamicImage, mode: InputAlpha) {
+        if mode == InputAlpha::Ignore || !img.color().has_alpha() {
+            return;
+        }
+
+        let input = img.to_luma_alpha16();
+        alpha_buffer.par_iter_mut().zip(input.par_chunks_exact(2)).for_each(|(alpha, pixel)| {
+            let input_alpha = pixel[1] as f32 / u16::MAX as f32;
+            *alpha = match mode {
+                InputAlpha::Min => alpha.min(input_alpha),
+                _ => *alpha * input_alpha,
+            };
+        });
+    }
+
+    /// Combines the original colors with the final alpha and encodes the RGBA cutout
+    pub fn process_output(&self, alpha_buffer: &[f32], img: &DynamicImage, encoding: &EncodingOptions) -> Result<Vec<u8>, AppError> {
+        if encoding.high_bit_depth {
+            let img_rgba = img.to_rgba16();
+            let mut rgba_data = vec![0u8; alpha_buffer.len() * 8];
+            rgba_data.par_chunks_exact_mut(8).zip(img_rgba.par_chunks_exact(4)).enumerate().for_each(|(i, (chunk, pixel))| {
+                let alpha = (alpha_buffer[i] * u16::MAX as f32).round() as u16;
+                for (bytes, value) in chunk.chunks_exact_mut(2).zip([pixel[0], pixel[1], pixel[2], alpha]) {
+                    bytes.copy_from_slice(&value.to_ne_bytes());
+                }
+            });
+            return self.encode(&rgba_data, img.width(), img.height(), image::ColorType::Rgba16, encoding);
+        }
+
+        let img_rgba = img.to_rgba8();
+        let rgba_buffer = img_rgba.as_raw();
+        let mut rgba_data = vec![0u8; alpha_buffer.len() * 4];
+
+        rgba_data.par_chunks_exact_mut(4).enumerate().for_each(|(i, chunk)| {
             let pixel_start = i * 4;
             chunk[0] = rgba_buffer[pixel_start];
             chunk[1] = rgba_buffer[pixel_start + 1];
             chunk[2] = rgba_buffer[pixel_start + 2];
-            chunk[3] = (smoothed_alpha * 255.0).round() as u8;
+            chunk[3] = (alpha_buffer[i] * 255.0).round() as u8;
+        });
+
+        self.encode(&rgba_data, img.width(), img.height(), image::ColorType::Rgba8, encoding)
+    }
+
+    /// Encodes the alpha on its own as a single-channel grayscale image
+    pub fn process_mask(&self, alpha_buffer: &[f32], width: u32, height: u32, encoding: &EncodingOptions) -> Result<Vec<u8>, AppError> {
+        if encoding.high_bit_depth {
+            let mask_data: Vec<u8> = alpha_buffer
+                .par_iter()
+                .flat_map_iter(|alpha| ((alpha * u16::MAX as f32).round() as u16).to_ne_bytes())
+                .collect();
+            return self.encode(&mask_data, width, height, image::ColorType::L16, encoding);
+        }
+
+        let mask_data: Vec<u8> = alpha_buffer
+            .par_iter()
+            .map(|alpha| (alpha * 255.0).round() as u8)
+            .collect();
+
+        self.encode(&mask_data, width, height, image::ColorType::L8, encoding)
+    }
+
+    /// Encodes an opaque image, such as a cutout composited over a new background
+    pub fn process_composite(&self, composite: &RgbImage, encoding: &EncodingOptions) -> Result<Vec<u8>, AppError> {
+        self.encode(composite.as_raw(), composite.width(), composite.height(), image::ColorType::Rgb8, encoding)
+    }
+
+    fn encode(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color_type: image::ColorType,
+        encoding: &EncodingOptions,
+    ) -> Result<Vec<u8>, AppError> {
+        // TIFF needs a seekable writer; 16-bit data is in native byte order
+        let mut output_buffer = Cursor::new(Vec::with_capacity(data.len()));
+
+        let compression = encoding.png_compression.unwrap_or_default();
+        let filter = encoding.png_filter.unwrap_or_default();
+        match encoding.format {
+            OutputFormat::Png if encoding.png_palette && color_type != image::ColorType::L8 => {
+                return self.encode_indexed_png(data, width, height, color_type, compression, filter);
+            }
+            OutputFormat::Png => {
+                let compression = match compression {
+                    PngCompression::Fast => CompressionType::Fast,
+                    PngCompression::Default => CompressionType::Default,
+                    PngCompression::Best => CompressionType::Best,
+                };
+                let filter = match filter {
+                    PngFilter::None => FilterType::NoFilter,
+                    PngFilter::Sub => FilterType::Sub,
+                    PngFilter::Up => FilterType::Up,
+                    PngFilter::Avg => FilterType::Avg,
+                    PngFilter::Paeth => FilterType::Paeth,
+                    PngFilter::Adaptive => FilterType::Adaptive,
+                };
+                PngEncoder::new_with_quality(&mut output_buffer, compression, filter)
+                    .write_image(data, width, height, color_type)
+            }
+            OutputFormat::WebP if encoding.lossless => {
+                WebPEncoder::new_lossless(&mut output_buffer).write_image(data, width, height, color_type)
+            }
+            OutputFormat::WebP => return self.encode_lossy_webp(data, width, height, color_type, encoding.quality),
+            OutputFormat::Jpeg => JpegEncoder::new_with_quality(&mut output_buffer, encoding.quality)
+                .write_image(data, width, height, color_type),
+            OutputFormat::Tiff => TiffEncoder::new(&mut output_buffer).write_image(data, width, height, color_type),
+        }
+        .map_err(|e| AppError::ImageProcessingError(e.to_string()))?;
+
+        Ok(output_buffer.into_inner())
+    }
+
+    /// Lossy WebP through libwebp; alpha is kept and compressed separately
+    fn encode_lossy_webp(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color_type: image::ColorType,
+        quality: u8,
+    ) -> Result<Vec<u8>, AppError> {
+        let expanded: Vec<u8>;
+        let (pixels, layout) = match color_type {
+            image::ColorType::Rgb8 => (data, PixelLayout::Rgb),
+            image::ColorType::Rgba8 => (data, PixelLayout::Rgba),
+            // Masks are gray; libwebp only takes RGB(A)
+            image::ColorType::L8 => {
+                expanded = data.iter().flat_map(|&value| [value; 3]).collect();
+                (expanded.as_slice(), PixelLayout::Rgb)
+            }
+            other => {
+                return Err(AppError::ImageProcessingError(format!("Unsupported WebP pixel layout: {:?}", other)));
+            }
+        };
+
+        let encoded = webp::Encoder::new(pixels, layout, width, height)
+            .encode_simple(false, quality as f32)
+            .map_err(|e| AppError::ImageProcessingError(format!("WebP encoding failed: {:?}", e)))?;
+        Ok(encoded.to_vec())
+    }
+
+    /// Quantizes RGB(A) pixels to a palette with NeuQuant and writes an indexed PNG with
+    /// per-entry transparency
+    fn encode_indexed_png(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color_type: image::ColorType,
+        compression: PngCompression,
+        filter: PngFilter,
+    ) -> Result<Vec<u8>, AppError> {
+        // Fully transparent pixels share one color so they do not spend palette entries
+        let rgba: Vec<u8> = data
+            .par_chunks_exact(color_type.bytes_per_pixel() as usize)
+            .flat_map_iter(|pixel| {
+                let alpha = pixel.get(3).copied().unwrap_or(u8::MAX);
+                if alpha == 0 { [0, 0, 0, 0] } else { [pixel[0], pixel[1], pixel[2], alpha] }
+            })
+            .collect();
+
+        let quantizer = NeuQuant::new(PALETTE_SAMPLE_FACTOR, PALETTE_SIZE, &rgba);
+        let indices: Vec<u8> = rgba.par_chunks_exact(4).map(|pixel| quantizer.index_of(pixel) as u8).collect();
+        let color_map = quantizer.color_map_rgba();
+        let palette: Vec<u8> = color_map.chunks_exact(4).flat_map(|entry| [entry[0], entry[1], entry[2]]).collect();
+        let transparency: Vec<u8> = color_map.chunks_exact(4).map(|entry| entry[3]).collect();
+
+        let mut output_buffer = Vec::with_capacity(indices.len());
+        let mut encoder = png::Encoder::new(&mut output_buffer, width, height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette);
+        encoder.set_trns(transparency);
+        encoder.set_compression(match compression {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Default => png::Compression::Default,
+            PngCompression::Best => png::Compression::Best,
         });
+        match filter {
+            PngFilter::None => encoder.set_filter(png::FilterType::NoFilter),
+            PngFilter::Sub => encoder.set_filter(png::FilterType::Sub),
+            PngFilter::Up => encoder.set_filter(png::FilterType::Up),
+            PngFilter::Avg => encoder.set_filter(png::FilterType::Avg),
+            PngFilter::Paeth => encoder.set_filter(png::FilterType::Paeth),
+            PngFilter::Adaptive => encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive),
+        }
 
-        encoder.write_image(
-            &rgba_data,
-            orig_width,
-            orig_height,
-            image::ColorType::Rgba8,
-        ).map_err(|e| AppError::ImageProcessingError(e.to_string()))?;
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&indices).and_then(|_| writer.finish()))
+            .map_err(|e| AppError::ImageProcessingError(e.to_string()))?;
 
         Ok(output_buffer)
     }
@@ -124,3 +426,148 @@ impl ImagePostprocessorV2 {
         score.abs().min(1.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::ColorType;
+    use super::*;
+
+    const WIDTH: u32 = 10;
+    const HEIGHT: u32 = 8;
+
+    fn postprocessor() -> ImagePostprocessorV2 {
+        ImagePostprocessorV2::new(320, 320, false)
+    }
+
+    /// Opaque 3x3 subject at (2, 3) on a transparent 10x8 alpha
+    fn subject_alpha() -> Vec<f32> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let (x, y) = (i % WIDTH, i / WIDTH);
+                if (2..=4).contains(&x) && (3..=5).contains(&y) { 1.0 } else { 0.0 }
+            })
+            .collect()
+    }
+
+    fn crop(options: CropOptions, limits: &DecodeLimits) -> Result<Option<CropRegion>, AppError> {
+        postprocessor().crop_region(&subject_alpha(), WIDTH, &options, limits)
+    }
+
+    fn region(x: i64, y: i64, width: u32, height: u32) -> Option<CropRegion> {
+        Some(CropRegion { x, y, width, height })
+    }
+
+    /// 4x3 RGBA test pattern with varying colors and alpha
+    fn rgba_pixels() -> Vec<u8> {
+        (0..12u8).flat_map(|i| [i * 20, 255 - i * 20, 128, 255 - i * 10]).collect()
+    }
+
+    fn encode(pixels: &[u8], color_type: ColorType, encoding: EncodingOptions) -> DynamicImage {
+        let encoded = postprocessor().encode(pixels, 4, 3, color_type, &encoding).unwrap();
+        image::load_from_memory(&encoded).unwrap()
+    }
+
+    fn max_difference(a: &[u8], b: &[u8]) -> u8 {
+        a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or_default()
+    }
+
+    #[test]
+    fn crops_to_the_subject() {
+        let limits = DecodeLimits::default();
+        assert_eq!(crop(CropOptions::default(), &limits).unwrap(), region(2, 3, 3, 3));
+
+        let padded = CropOptions { padding: CropPadding::Pixels(2), ..CropOptions::default() };
+        assert_eq!(crop(padded, &limits).unwrap(), region(0, 1, 7, 7));
+
+        // Percentages are of the longer side of the subject, and the canvas may leave the image
+        let percent = CropOptions { padding: CropPadding::Percent(100.0), ..CropOptions::default() };
+        assert_eq!(crop(percent, &limits).unwrap(), region(-1, 0, 9, 9));
+
+        let wide = CropOptions { aspect_ratio: Some(2.0), ..CropOptions::default() };
+        assert_eq!(crop(wide, &limits).unwrap(), region(1, 3, 6, 3));
+        let tall = CropOptions { aspect_ratio: Some(0.5), ..CropOptions::default() };
+        assert_eq!(crop(tall, &limits).unwrap(), region(2, 2, 3, 6));
+    }
+
+    #[test]
+    fn crop_ignores_alpha_below_threshold() {
+        let faint = vec![0.1; (WIDTH * HEIGHT) as usize];
+        let options = CropOptions { alpha_threshold: 128, ..CropOptions::default() };
+        let cropped = postprocessor().crop_region(&faint, WIDTH, &options, &DecodeLimits::default());
+        assert_eq!(cropped.unwrap(), None);
+    }
+
+    #[test]
+    fn crop_is_held_to_the_decode_limits() {
+        let limits = DecodeLimits { max_width: 6, ..DecodeLimits::default() };
+        let padded = CropOptions { padding: CropPadding::Pixels(2), ..CropOptions::default() };
+        assert!(matches!(crop(padded, &limits), Err(AppError::ImageTooLarge(_))));
+
+        let limits = DecodeLimits { max_pixels: 48, ..DecodeLimits::default() };
+        assert!(matches!(crop(padded, &limits), Err(AppError::ImageTooLarge(_))));
+
+        let huge = CropOptions { padding: CropPadding::Pixels(u32::MAX), ..CropOptions::default() };
+        assert!(matches!(crop(huge, &DecodeLimits::default()), Err(AppError::ImageTooLarge(_))));
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let pixels = rgba_pixels();
+        assert_eq!(encode(&pixels, ColorType::Rgba8, EncodingOptions::default()).to_rgba8().into_raw(), pixels);
+    }
+
+    #[test]
+    fn png_keeps_16_bits() {
+        // 16-bit data is passed in native byte order
+        let wide: Vec<u16> = rgba_pixels().iter().map(|&value| (value as u16) << 8 | 0x5a).collect();
+        let bytes: Vec<u8> = wide.iter().flat_map(|value| value.to_ne_bytes()).collect();
+        let decoded = encode(&bytes, ColorType::Rgba16, EncodingOptions::default());
+        assert_eq!(decoded.color(), ColorType::Rgba16);
+        assert_eq!(decoded.to_rgba16().into_raw(), wide);
+    }
+
+    #[test]
+    fn palette_png_keeps_few_colors() {
+        // Quadrants of flat colors, one of them fully transparent; NeuQuant needs more than a
+        // handful of pixels to learn from
+        let colors = [[255, 0, 0, 255], [0, 0, 255, 128], [40, 200, 40, 64], [0, 0, 0, 0]];
+        let pixels: Vec<u8> = (0..32 * 32u32)
+            .flat_map(|i| colors[((i % 32) / 16 + (i / 32) / 16 * 2) as usize])
+            .collect();
+        let encoding = EncodingOptions { png_palette: true, ..EncodingOptions::default() };
+        let encoded = postprocessor().encode(&pixels, 32, 32, ColorType::Rgba8, &encoding).unwrap();
+        let decoded = image::load_from_memory(&encoded).unwrap();
+        assert_eq!(decoded.color(), ColorType::Rgba8);
+        assert!(max_difference(&decoded.to_rgba8(), &pixels) <= 8);
+    }
+
+    #[test]
+    fn jpeg_round_trips() {
+        let pixels: Vec<u8> = rgba_pixels().chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect();
+        let encoding = EncodingOptions { format: OutputFormat::Jpeg, quality: 100, ..EncodingOptions::default() };
+        let decoded = encode(&pixels, ColorType::Rgb8, encoding);
+        assert_eq!(decoded.color(), ColorType::Rgb8);
+        assert_eq!((decoded.width(), decoded.height()), (4, 3));
+        assert!(max_difference(&decoded.to_rgb8(), &pixels) <= 32);
+    }
+
+    #[test]
+    fn webp_round_trips() {
+        let pixels = rgba_pixels();
+        let lossless = EncodingOptions { format: OutputFormat::WebP, ..EncodingOptions::default() };
+        assert_eq!(encode(&pixels, ColorType::Rgba8, lossless).to_rgba8().into_raw(), pixels);
+
+        let lossy = EncodingOptions { lossless: false, quality: 100, ..lossless };
+        let decoded = encode(&pixels, ColorType::Rgba8, lossy);
+        assert!(decoded.color().has_alpha());
+        let decoded = decoded.to_rgba8();
+        assert_eq!(decoded.dimensions(), (4, 3));
+        let alpha_difference = decoded.pixels().zip(pixels.chunks_exact(4)).map(|(a, b)| a[3].abs_diff(b[3])).max();
+        assert!(alpha_difference.unwrap_or_default() <= 2);
+
+        let mask: Vec<u8> = (0..12).map(|i| i * 20).collect();
+        let decoded = encode(&mask, ColorType::L8, lossy).to_luma8();
+        assert_eq!(decoded.dimensions(), (4, 3));
+        assert!(max_difference(&decoded, &mask) <= 32);
+    }
+}
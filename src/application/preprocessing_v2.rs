@@ -1,18 +1,28 @@
 use image::DynamicImage;
 use ndarray::Array4;
 use crate::domain::AppError;
-use super::constants::preprocessing::*;
+use super::model_registry::Normalization;
+
+/// Original size, resized size and padding offset of an image inside the model input
+pub type LetterboxDimensions = ((u32, u32), (u32, u32), (u32, u32));
 
 pub struct ImagePreprocessorV2 {
     pixel_size: u32,
+    scale: [f32; 3],
+    offset: [f32; 3],
 }
 
 impl ImagePreprocessorV2 {
-    pub fn new(pixel_size: u32) -> Self {
-        Self { pixel_size }
+    pub fn new(pixel_size: u32, normalization: Normalization) -> Self {
+        let Normalization { mean, std } = normalization;
+        Self {
+            pixel_size,
+            scale: std::array::from_fn(|c| 1.0 / (255.0 * std[c])),
+            offset: std::array::from_fn(|c| -mean[c] / std[c]),
+        }
     }
 
-    pub fn prepare_for_inference(&self, img: &DynamicImage) -> Result<(Array4<f32>, (u32, u32), (u32, u32), (u32, u32)), AppError> {
+    pub fn prepare_for_inference(&self, img: &DynamicImage) -> Result<(Array4<f32>, LetterboxDimensions), AppError> {
         let (orig_width, orig_height) = (img.width(), img.height());
         let (resize_width, resize_height) = self.calculate_dimensions(orig_width, orig_height);
         
@@ -39,9 +49,9 @@ impl ImagePreprocessorV2 {
             let g = unsafe { pixel.get_unchecked(1) };
             let b = unsafe { pixel.get_unchecked(2) };
             
-            let normalized_r = (*r as f32) * SCALE_R + OFFSET_R;
-            let normalized_g = (*g as f32) * SCALE_G + OFFSET_G;
-            let normalized_b = (*b as f32) * SCALE_B + OFFSET_B;
+            let normalized_r = (*r as f32) * self.scale[0] + self.offset[0];
+            let normalized_g = (*g as f32) * self.scale[1] + self.offset[1];
+            let normalized_b = (*b as f32) * self.scale[2] + self.offset[2];
             
             unsafe {
                 *input_tensor.uget_mut([0, 0, tensor_y, tensor_x]) = normalized_r;
@@ -50,7 +60,7 @@ impl ImagePreprocessorV2 {
             }
         }
 
-        Ok((input_tensor, (orig_width, orig_height), (resize_width, resize_height), (start_x, start_y)))
+        Ok((input_tensor, ((orig_width, orig_height), (resize_width, resize_height), (start_x, start_y))))
     }
 
     fn calculate_dimensions(&self, orig_width: u32, orig_height: u32) -> (u32, u32) {
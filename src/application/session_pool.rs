@@ -0,0 +1,82 @@
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use ndarray::ArrayView4;
+use crate::domain::AppError;
+use super::inference_v2::{ModelInferenceV2, ModelMetadata};
+
+/// Fixed set of sessions for one model. Each concurrent inference checks out its own session,
+/// so requests only queue once every session is busy.
+pub struct SessionPool {
+    sessions: Mutex<Vec<ModelInferenceV2>>,
+    available: Condvar,
+    metadata: ModelMetadata,
+    size: usize,
+}
+
+/// Session checked out of a [`SessionPool`]; returned to the pool on drop
+pub struct PooledSession<'a> {
+    pool: &'a SessionPool,
+    session: Option<ModelInferenceV2>,
+}
+
+impl SessionPool {
+    pub fn new(model_path: &Path, fallback_size: u32, size: usize, intra_op_threads: usize) -> Result<Self, AppError> {
+        let size = size.max(1);
+        let sessions = (0..size)
+            .map(|_| ModelInferenceV2::new(model_path, fallback_size, intra_op_threads))
+            .collect::<Result<Vec<_>, _>>()?;
+        let metadata = sessions[0].metadata().clone();
+
+        Ok(Self {
+            sessions: Mutex::new(sessions),
+            available: Condvar::new(),
+            metadata,
+            size,
+        })
+    }
+
+    pub fn metadata(&self) -> &ModelMetadata {
+        &self.metadata
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Blocks until a session is free
+    pub fn acquire(&self) -> PooledSession<'_> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(session) = sessions.pop() {
+                return PooledSession { pool: self, session: Some(session) };
+            }
+            sessions = self.available.wait(sessions).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    pub fn run(&self, input: ArrayView4<f32>) -> Result<Vec<f32>, AppError> {
+        self.acquire().run(input)
+    }
+
+    fn release(&self, session: ModelInferenceV2) {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner()).push(session);
+        self.available.notify_one();
+    }
+}
+
+impl Deref for PooledSession<'_> {
+    type Target = ModelInferenceV2;
+
+    fn deref(&self) -> &Self::Target {
+        self.session.as_ref().expect("pooled session is present until drop")
+    }
+}
+
+impl Drop for PooledSession<'_> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.release(session);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use image::DynamicImage;
+use rayon::prelude::*;
+use crate::domain::AppError;
+use super::model_registry::ModelPipeline;
+use super::constants::high_res::*;
+
+/// Region of the original image covered by one tile
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// High-resolution mask refinement: after the coarse pass, the model is re-run on overlapping
+/// tiles around the detected subject boundary and the tile masks are blended back into the
+/// full-resolution alpha.
+pub struct TiledRefiner;
+
+impl TiledRefiner {
+    pub fn refine(model: &ModelPipeline, img: &DynamicImage, alpha: &mut [f32]) -> Result<(), AppError> {
+        let (width, height) = (img.width(), img.height());
+        let input_size = model.inference.metadata().input_size;
+        let longest_side = width.max(height);
+        if longest_side <= input_size {
+            // The coarse pass already ran at full resolution
+            return Ok(());
+        }
+
+        let tile_size = input_size.max(longest_side.div_ceil(MAX_TILES_PER_SIDE));
+        let stride = ((tile_size as f32 * (1.0 - TILE_OVERLAP)) as u32).max(1);
+        let feather = (tile_size - stride).max(1) as f32;
+
+        let uncertain = UncertaintyMap::new(alpha, width as usize, height as usize);
+        let band_radius = (tile_size / BAND_RADIUS_DIVISOR) as usize;
+
+        let tiles: Vec<Tile> = Self::tile_positions(height, tile_size, stride)
+            .into_iter()
+            .flat_map(|y| {
+                Self::tile_positions(width, tile_size, stride).into_iter().map(move |x| Tile {
+                    x,
+                    y,
+                    width: tile_size.min(width - x),
+                    height: tile_size.min(height - y),
+                })
+            })
+            .filter(|tile| uncertain.count(
+                tile.x as usize, tile.y as usize,
+                (tile.x + tile.width) as usize, (tile.y + tile.height) as usize,
+            ) > 0)
+            .collect();
+
+        if tiles.is_empty() {
+            return Ok(());
+        }
+        tracing::debug!("High-res refinement on {} tiles of {}px", tiles.len(), tile_size);
+
+        // Tiles run concurrently so the batch scheduler can combine them into larger inferences
+        let tile_masks = tiles
+            .par_iter()
+            .map(|tile| Self::run_tile(model, img, tile))
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        let width_usize = width as usize;
+        let mut accumulated = vec![0f32; alpha.len()];
+        let mut weights = vec![0f32; alpha.len()];
+
+        for (tile, mask) in tiles.iter().zip(&tile_masks) {
+            for ty in 0..tile.height {
+                let wy = Self::edge_weight(ty, tile.y, tile.height, height, feather);
+                let row = (tile.y + ty) as usize * width_usize + tile.x as usize;
+                let mask_row = ty as usize * tile.width as usize;
+
+                for tx in 0..tile.width {
+                    let weight = wy * Self::edge_weight(tx, tile.x, tile.width, width, feather);
+                    let i = row + tx as usize;
+                    accumulated[i] += weight * mask[mask_row + tx as usize];
+                    weights[i] += weight;
+                }
+            }
+        }
+
+        // Only pixels near the coarse boundary take tile values; tiles see less context than the
+        // coarse pass, so confident regions far from any edge keep the coarse prediction.
+        alpha.par_iter_mut().enumerate().for_each(|(i, value)| {
+            let weight = weights[i];
+            if weight <= 0.0 {
+                return;
+            }
+
+            let (x, y) = (i % width_usize, i / width_usize);
+            if uncertain.count(
+                x.saturating_sub(band_radius), y.saturating_sub(band_radius),
+                x + band_radius + 1, y + band_radius + 1,
+            ) == 0 {
+                return;
+            }
+
+            let blend = weight.min(1.0);
+            *value = (accumulated[i] / weight) * blend + *value * (1.0 - blend);
+        });
+
+        Ok(())
+    }
+
+    fn run_tile(model: &ModelPipeline, img: &DynamicImage, tile: &Tile) -> Result<Vec<f32>, AppError> {
+        let tile_img = img.crop_imm(tile.x, tile.y, tile.width, tile.height);
+        let (input_tensor, dimensions) = model.preprocessor.prepare_for_inference(&tile_img)?;
+        let outputs = model.inference.run(input_tensor.view())?;
+        Ok(model.postprocessor.upsample_mask(&outputs, dimensions))
+    }
+
+    /// Start offsets of tiles covering `length` pixels; the last tile is aligned to the far edge
+    fn tile_positions(length: u32, tile_size: u32, stride: u32) -> Vec<u32> {
+        if length <= tile_size {
+            return vec![0];
+        }
+
+        let mut positions: Vec<u32> = (0..length - tile_size).step_by(stride as usize).collect();
+        positions.push(length - tile_size);
+        positions
+    }
+
+    /// Linear ramp from 0 at a tile edge to 1 after `feather` pixels, except on image borders
+    fn edge_weight(offset: u32, tile_start: u32, tile_length: u32, image_length: u32, feather: f32) -> f32 {
+        let mut weight: f32 = 1.0;
+        if tile_start > 0 {
+            weight = weight.min((offset + 1) as f32 / feather);
+        }
+        if tile_start + tile_length < image_length {
+            weight = weight.min((tile_length - offset) as f32 / feather);
+        }
+        weight
+    }
+}
+
+/// Summed-area table of pixels whose coarse alpha is neither clearly foreground nor background
+struct UncertaintyMap {
+    table: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl UncertaintyMap {
+    fn new(alpha: &[f32], width: usize, height: usize) -> Self {
+        let stride = width + 1;
+        let mut table = vec![0u32; stride * (height + 1)];
+
+        for y in 0..height {
+            let mut row_sum = 0u32;
+            for x in 0..width {
+                let value = alpha[y * width + x];
+                row_sum += u32::from(value > BOUNDARY_ALPHA_MIN && value < BOUNDARY_ALPHA_MAX);
+                table[(y + 1) * stride + x + 1] = table[y * stride + x + 1] + row_sum;
+            }
+        }
+
+        Self { table, width, height }
+    }
+
+    /// Number of uncertain pixels in `[x0, x1) x [y0, y1)`, clamped to the image
+    fn count(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> u32 {
+        let (x1, y1) = (x1.min(self.width), y1.min(self.height));
+        if x0 >= x1 || y0 >= y1 {
+            return 0;
+        }
+
+        let stride = self.width + 1;
+        self.table[y1 * stride + x1] + self.table[y0 * stride + x0]
+            - self.table[y0 * stride + x1]
+            - self.table[y1 * stride + x0]
+    }
+}
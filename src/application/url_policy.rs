@@ -0,0 +1,241 @@
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use url::Host;
+use super::constants::fetch::*;
+
+/// Checks made on outgoing URLs and every redirect target
+pub struct UrlPolicy {
+    allowed_hosts: Vec<String>,
+    allow_private_addresses: bool,
+}
+
+impl UrlPolicy {
+    /// An empty allow-list allows every host
+    pub fn new(allowed_hosts: &[String], allow_private_addresses: bool) -> Self {
+        Self {
+            allowed_hosts: allowed_hosts.iter().map(|host| host.trim().to_ascii_lowercase()).collect(),
+            allow_private_addresses,
+        }
+    }
+
+    pub fn check(&self, url: &Url) -> Result<(), BlockedUrl> {
+        if !ALLOWED_SCHEMES.contains(&url.scheme()) {
+            return Err(BlockedUrl(format!("Unsupported URL scheme: {}", url.scheme())));
+        }
+        let host = url.host().ok_or_else(|| BlockedUrl("URL has no host".to_string()))?;
+        let host_name = url.host_str().unwrap_or_default().to_ascii_lowercase();
+
+        if !self.allowed_hosts.is_empty() && !self.allowed_hosts.iter().any(|allowed| host_matches(&host_name, allowed)) {
+            return Err(BlockedUrl(format!("Host is not allowed: {}", host_name)));
+        }
+
+        // Names are checked when resolved; IP literals never reach the resolver
+        let address = match host {
+            Host::Ipv4(address) => IpAddr::V4(address),
+            Host::Ipv6(address) => IpAddr::V6(address),
+            Host::Domain(_) => return Ok(()),
+        };
+        if !self.allow_private_addresses && !is_public(address) {
+            return Err(BlockedUrl(format!("Address is not public: {}", address)));
+        }
+        Ok(())
+    }
+}
+
+/// `*.example.com` matches subdomains of `example.com`, other entries match exactly
+fn host_matches(host: &str, allowed: &str) -> bool {
+    match allowed.strip_prefix(WILDCARD_PREFIX) {
+        Some(domain) => host.strip_suffix(domain).is_some_and(|prefix| prefix.ends_with('.')),
+        None => host == allowed,
+    }
+}
+
+/// Resolves names with the system resolver and keeps only public addresses
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|address| is_public(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(BlockedUrl(format!("Host does not resolve to a public address: {}", host)).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether an address is reachable on the public internet, as opposed to loopback, private,
+/// shared (CGNAT), link-local (cloud metadata), documentation, multicast or reserved ranges
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => is_public_v4(address),
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(address),
+        },
+    }
+}
+
+fn is_public_v4(address: Ipv4Addr) -> bool {
+    let [a, b, _, _] = address.octets();
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_broadcast()
+        || address.is_documentation()
+        || address.is_multicast()
+        || a == 0
+        // Shared address space 100.64.0.0/10
+        || (a == 100 && (b & 0xc0) == 64)
+        // Benchmarking 198.18.0.0/15
+        || (a == 198 && (b & 0xfe) == 18)
+        // Reserved 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_v6(address: Ipv6Addr) -> bool {
+    let segments = address.segments();
+    let embedded = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+    match segments {
+        // IPv4-compatible ::a.b.c.d, deprecated but still routed by some stacks
+        [0, 0, 0, 0, 0, 0, high, low] if !address.is_unspecified() && !address.is_loopback() => {
+            is_public_v4(embedded(high, low))
+        }
+        // 6to4 2002::/16 tunnels to the IPv4 address in the next 32 bits
+        [0x2002, high, low, ..] => is_public_v4(embedded(high, low)),
+        // Teredo 2001::/32 hides an obfuscated client address
+        [0x2001, 0, ..] => false,
+        // NAT64 64:ff9b::/96 and local-use 64:ff9b:1::/48 can reach any IPv4 address
+        [0x0064, 0xff9b, ..] => false,
+        _ => !(address.is_unspecified()
+            || address.is_loopback()
+            || address.is_multicast()
+            // Unique local fc00::/7
+            || (segments[0] & 0xfe00) == 0xfc00
+            // Link-local fe80::/10
+            || (segments[0] & 0xffc0) == 0xfe80
+            // Documentation 2001:db8::/32
+            || (segments[0] == 0x2001 && segments[1] == 0x0db8)),
+    }
+}
+
+/// Why a request failed: the refusal when a redirect target or resolved address was blocked,
+/// otherwise the innermost cause, which carries the most specific message
+pub enum RequestFailure<'a> {
+    Blocked(&'a BlockedUrl),
+    Other(&'a (dyn Error + 'static)),
+}
+
+pub fn request_failure(error: &reqwest::Error) -> RequestFailure<'_> {
+    let mut cause: &(dyn Error + 'static) = error;
+    loop {
+        if let Some(blocked) = cause.downcast_ref::<BlockedUrl>() {
+            return RequestFailure::Blocked(blocked);
+        }
+        match cause.source() {
+            Some(source) => cause = source,
+            None => return RequestFailure::Other(cause),
+        }
+    }
+}
+
+/// Reason a URL or address was refused
+#[derive(Debug)]
+pub struct BlockedUrl(pub String);
+
+impl fmt::Display for BlockedUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for BlockedUrl {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(policy: &UrlPolicy, url: &str) -> Result<(), BlockedUrl> {
+        policy.check(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn check_rejects_other_schemes() {
+        let policy = UrlPolicy::new(&[], false);
+        assert!(check(&policy, "https://example.com/a.png").is_ok());
+        assert!(check(&policy, "ftp://example.com/a.png").is_err());
+        assert!(check(&policy, "file:///etc/passwd").is_err());
+    }
+
+    #[test]
+    fn check_applies_allow_list() {
+        let policy = UrlPolicy::new(&[" Images.example.org ".to_string(), "*.cdn.example.com".to_string()], false);
+        assert!(check(&policy, "https://images.example.org/a.png").is_ok());
+        assert!(check(&policy, "https://a.cdn.example.com/a.png").is_ok());
+        assert!(check(&policy, "https://a.b.cdn.example.com/a.png").is_ok());
+        assert!(check(&policy, "https://cdn.example.com/a.png").is_err());
+        assert!(check(&policy, "https://evilcdn.example.com/a.png").is_err());
+        assert!(check(&policy, "https://example.org/a.png").is_err());
+    }
+
+    #[test]
+    fn check_rejects_non_public_literals() {
+        let policy = UrlPolicy::new(&[], false);
+        for url in [
+            "http://127.0.0.1/",
+            "http://10.0.0.1/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/",
+            "http://[::ffff:192.168.0.1]/",
+        ] {
+            assert!(check(&policy, url).is_err(), "{}", url);
+        }
+        assert!(check(&policy, "http://93.184.216.34/").is_ok());
+        assert!(check(&policy, "http://localhost/").is_ok(), "names are checked when resolved");
+
+        let permissive = UrlPolicy::new(&[], true);
+        assert!(check(&permissive, "http://127.0.0.1/").is_ok());
+        assert!(check(&permissive, "http://169.254.169.254/").is_ok());
+    }
+
+    #[test]
+    fn public_v4() {
+        for address in ["8.8.8.8", "93.184.216.34", "100.128.0.1", "198.20.0.1"] {
+            assert!(is_public_v4(address.parse().unwrap()), "{}", address);
+        }
+        for address in [
+            "0.0.0.0", "0.1.2.3", "127.0.0.1", "10.1.2.3", "172.16.0.1", "172.31.255.255", "192.168.1.1",
+            "169.254.169.254", "100.64.0.1", "100.127.255.255", "198.18.0.1", "192.0.2.1", "224.0.0.1",
+            "240.0.0.1", "255.255.255.255",
+        ] {
+            assert!(!is_public_v4(address.parse().unwrap()), "{}", address);
+        }
+    }
+
+    #[test]
+    fn public_v6() {
+        for address in ["2606:4700:4700::1111", "::ffff:8.8.8.8", "::8.8.8.8", "2002:808:808::1"] {
+            assert!(is_public(address.parse().unwrap()), "{}", address);
+        }
+        for address in [
+            "::", "::1", "fe80::1", "fc00::1", "fd12:3456::1", "ff02::1", "2001:db8::1",
+            // IPv4-mapped and IPv4-compatible
+            "::ffff:127.0.0.1", "::ffff:169.254.169.254", "::10.0.0.1",
+            // 6to4 of 127.0.0.1 and 192.168.0.1
+            "2002:7f00:1::", "2002:c0a8:1::1",
+            // Teredo and NAT64
+            "2001:0:4136:e378::1", "64:ff9b::a00:1", "64:ff9b:1::1",
+        ] {
+            assert!(!is_public(address.parse().unwrap()), "{}", address);
+        }
+    }
+}
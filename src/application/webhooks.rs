@@ -0,0 +1,451 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
+use reqwest::{Client, StatusCode, Url};
+use serde::Serialize;
+use sha2::Sha256;
+use crate::domain::AppError;
+use super::config::WebhookConfig;
+use super::constants::webhooks::*;
+use super::jobs::{ImageStatus, JobState};
+use super::url_policy::{request_failure, PublicResolver, RequestFailure, UrlPolicy};
+
+/// Callback requested with a job
+#[derive(Debug, Clone)]
+pub struct Callback {
+    pub url: String,
+    /// Embed the result images in the payload as base64
+    pub include_images: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryState {
+    Pending,
+    Delivered,
+    /// Every attempt failed or the receiver answered with a status that is not retried
+    Failed,
+}
+
+impl DeliveryState {
+    pub fn name(self) -> &'static str {
+        match self {
+            DeliveryState::Pending => DELIVERY_PENDING,
+            DeliveryState::Delivered => DELIVERY_DELIVERED,
+            DeliveryState::Failed => DELIVERY_FAILED,
+        }
+    }
+}
+
+/// One POST of a callback
+#[derive(Debug, Clone)]
+pub struct DeliveryAttempt {
+    pub attempt: u32,
+    pub sent_at: SystemTime,
+    pub duration: Duration,
+    /// Missing when no response arrived
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Delivery log of a job callback
+#[derive(Debug, Clone)]
+pub struct CallbackStatus {
+    pub url: String,
+    pub state: DeliveryState,
+    pub attempts: Vec<DeliveryAttempt>,
+}
+
+/// JSON body of a callback
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload {
+    pub event: &'static str,
+    pub job_id: String,
+    pub status: &'static str,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub images: Vec<WebhookImage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookImage {
+    pub index: usize,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Base64 result, only when the callback asked for images
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+impl WebhookPayload {
+    pub fn new(
+        job_id: &str,
+        state: JobState,
+        images: &[ImageStatus],
+        results: &[Option<Bytes>],
+        include_images: bool,
+    ) -> Self {
+        Self {
+            event: if state == JobState::Completed { EVENT_JOB_COMPLETED } else { EVENT_JOB_FAILED },
+            job_id: job_id.to_string(),
+            status: state.name(),
+            total: images.len(),
+            completed: images.iter().filter(|status| **status == ImageStatus::Completed).count(),
+            failed: images.iter().filter(|status| matches!(status, ImageStatus::Failed(_))).count(),
+            images: images
+                .iter()
+                .zip(results)
+                .enumerate()
+                .map(|(index, (status, result))| WebhookImage {
+                    index,
+                    status: status.name(),
+                    error: match status {
+                        ImageStatus::Failed(error) => Some(error.clone()),
+                        _ => None,
+                    },
+                    image: result.as_ref().filter(|_| include_images).map(|data| STANDARD.encode(data)),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Posts job results to client callback URLs. Bodies are signed with HMAC-SHA256 over
+/// `<timestamp>.<body>` so receivers can check their origin and reject replays; failed
+/// deliveries are retried with exponential backoff. Callback URLs pass the same host and
+/// address checks as `image_url`, and redirects are not followed.
+pub struct WebhookNotifier {
+    client: Client,
+    policy: UrlPolicy,
+    secret: Vec<u8>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl WebhookNotifier {
+    pub fn new(secret: &str, config: &WebhookConfig) -> Result<Self, AppError> {
+        let mut builder = Client::builder()
+            .timeout(config.attempt_timeout)
+            .no_proxy()
+            .redirect(Policy::none());
+        if !config.allow_private_addresses {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        let client = builder.build().map_err(|e| AppError::ModelError(e.to_string()))?;
+        Ok(Self {
+            client,
+            policy: UrlPolicy::new(&config.allowed_hosts, config.allow_private_addresses),
+            secret: secret.as_bytes().to_vec(),
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: config.initial_backoff,
+            max_backoff: config.max_backoff,
+        })
+    }
+
+    /// Validates a callback URL when the job is submitted; names are only resolved on delivery
+    pub fn check(&self, url: &str) -> Result<Url, AppError> {
+        let url = Url::parse(url.trim())
+            .map_err(|e| AppError::ImageProcessingError(format!("Invalid callback URL: {}", e)))?;
+        self.policy.check(&url).map_err(|blocked| AppError::UrlNotAllowed(blocked.0))?;
+        Ok(url)
+    }
+
+    /// Posts the payload until the receiver answers 2xx, answers with a status that is not
+    /// retried, or the attempts run out. Every attempt is passed to `on_attempt`; returns
+    /// the final state, `Delivered` or `Failed`.
+    pub async fn deliver(
+        &self,
+        url: &Url,
+        payload: &WebhookPayload,
+        mut on_attempt: impl FnMut(DeliveryAttempt),
+    ) -> DeliveryState {
+        let body = match serde_json::to_vec(payload) {
+            Ok(body) => Bytes::from(body),
+            Err(e) => {
+                tracing::error!("Failed to serialize callback for job {}: {}", payload.job_id, e);
+                return DeliveryState::Failed;
+            }
+        };
+
+        for attempt in 1..=self.max_attempts {
+            let sent_at = SystemTime::now();
+            let start = Instant::now();
+            let (status, error, retry) = match self.send(url, payload, attempt, body.clone()).await {
+                Ok(status) if status.is_success() => (Some(status.as_u16()), None, false),
+                Ok(status) => (Some(status.as_u16()), Some(format!("Callback returned {}", status)), is_retryable(status)),
+                Err(e) => {
+                    let (message, blocked) = describe_error(&e);
+                    (None, Some(message), !blocked)
+                }
+            };
+            let delivered = error.is_none();
+
+            if let Some(error) = &error {
+                tracing::warn!("Callback for job {} attempt {} failed: {}", payload.job_id, attempt, error);
+            }
+            on_attempt(DeliveryAttempt { attempt, sent_at, duration: start.elapsed(), status, error });
+
+            if delivered {
+                return DeliveryState::Delivered;
+            }
+            if !retry || attempt == self.max_attempts {
+                break;
+            }
+            tokio::time::sleep(self.backoff(attempt)).await;
+        }
+        DeliveryState::Failed
+    }
+
+    /// Wait after a failed attempt: `initial_backoff` doubled for every earlier failure, capped
+    /// at `max_backoff`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    async fn send(&self, url: &Url, payload: &WebhookPayload, attempt: u32, body: Bytes) -> reqwest::Result<StatusCode> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let response = self.client
+            .post(url.clone())
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON)
+            .header(HEADER_EVENT, payload.event)
+            .header(HEADER_JOB_ID, &payload.job_id)
+            .header(HEADER_ATTEMPT, attempt)
+            .header(HEADER_TIMESTAMP, timestamp)
+            .header(HEADER_SIGNATURE, self.sign(timestamp, &body))
+            .body(body)
+            .send()
+            .await?;
+        Ok(response.status())
+    }
+
+    fn sign(&self, timestamp: u64, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        format!("{}{}", SIGNATURE_PREFIX, hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+/// Server errors, rate limiting and timeouts are retried; other statuses, redirects included,
+/// would get the same answer again
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT
+}
+
+/// Message for a failed request, and whether the address was refused, which no retry changes
+fn describe_error(error: &reqwest::Error) -> (String, bool) {
+    match request_failure(error) {
+        RequestFailure::Blocked(blocked) => (blocked.0.clone(), true),
+        RequestFailure::Other(_) if error.is_timeout() => ("Callback timed out".to_string(), false),
+        RequestFailure::Other(cause) => (format!("Failed to send callback: {}", cause), false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    /// Headers, lowercased, and body of a request the receiver got
+    struct Received {
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> &str {
+            let name = name.to_ascii_lowercase();
+            self.headers
+                .iter()
+                .find(|(header, _)| *header == name)
+                .map(|(_, value)| value.as_str())
+                .unwrap_or_default()
+        }
+    }
+
+    /// Receiver on a local port that answers 503 to the first `failures` requests and 200 after
+    async fn receiver(failures: usize) -> (Url, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&received);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let Some(request) = read_request(&mut stream).await else {
+                    continue;
+                };
+                let count = {
+                    let mut log = log.lock().unwrap();
+                    log.push(request);
+                    log.len()
+                };
+                let status = if count > failures { "200 OK" } else { "503 Service Unavailable" };
+                let response = format!("HTTP/1.1 {}\r\nConnection: close\r\nContent-Length: 0\r\n\r\n", status);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, received)
+    }
+
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<Received> {
+        let mut data = Vec::new();
+        let mut buffer = [0; 4096];
+        let header_end = loop {
+            if let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+                break end;
+            }
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => return None,
+                Ok(read) => data.extend_from_slice(&buffer[..read]),
+            }
+        };
+
+        let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+        let headers: Vec<(String, String)> = head
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        let length: usize = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or_default();
+
+        let mut body = data[header_end + 4..].to_vec();
+        while body.len() < length {
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => return None,
+                Ok(read) => body.extend_from_slice(&buffer[..read]),
+            }
+        }
+        Some(Received { headers, body })
+    }
+
+    fn notifier(max_attempts: u32) -> WebhookNotifier {
+        let config = WebhookConfig {
+            allow_private_addresses: true,
+            max_attempts,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+            ..WebhookConfig::default()
+        };
+        WebhookNotifier::new(SECRET, &config).unwrap()
+    }
+
+    fn payload() -> WebhookPayload {
+        let images = [ImageStatus::Completed, ImageStatus::Failed("Unsupported image format".to_string())];
+        WebhookPayload::new("job", JobState::Completed, &images, &[Some(Bytes::from_static(b"png")), None], true)
+    }
+
+    fn expected_signature(timestamp: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(format!("{}.", timestamp).as_bytes());
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        let body = br#"{"job_id":"job"}"#;
+        let signature = notifier(1).sign(1_700_000_000, body);
+        assert_eq!(signature, expected_signature("1700000000", body));
+        assert_ne!(signature, notifier(1).sign(1_700_000_001, body));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let config = WebhookConfig {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            ..WebhookConfig::default()
+        };
+        let notifier = WebhookNotifier::new(SECRET, &config).unwrap();
+        let delays: Vec<u64> = (1..=8).map(|attempt| notifier.backoff(attempt).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(notifier.backoff(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn payload_embeds_requested_images() {
+        let json = serde_json::to_value(payload()).unwrap();
+        assert_eq!(json["event"], EVENT_JOB_COMPLETED);
+        assert_eq!(json["status"], "completed");
+        assert_eq!(json["total"], 2);
+        assert_eq!(json["completed"], 1);
+        assert_eq!(json["failed"], 1);
+        assert_eq!(json["images"][0]["image"], STANDARD.encode(b"png"));
+        assert_eq!(json["images"][1]["error"], "Unsupported image format");
+        assert!(json["images"][1].get("image").is_none());
+    }
+
+    #[tokio::test]
+    async fn retries_until_delivered() {
+        let (url, received) = receiver(2).await;
+        let mut attempts = Vec::new();
+        let state = notifier(5).deliver(&url, &payload(), |attempt| attempts.push(attempt)).await;
+
+        assert_eq!(state, DeliveryState::Delivered);
+        let log: Vec<(u32, Option<u16>, bool)> =
+            attempts.iter().map(|attempt| (attempt.attempt, attempt.status, attempt.error.is_some())).collect();
+        assert_eq!(log, [(1, Some(503), true), (2, Some(503), true), (3, Some(200), false)]);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        for (index, request) in received.iter().enumerate() {
+            assert_eq!(request.header(HEADER_ATTEMPT), (index + 1).to_string());
+            assert_eq!(request.header(HEADER_EVENT), EVENT_JOB_COMPLETED);
+            assert_eq!(request.header(HEADER_JOB_ID), "job");
+            assert_eq!(request.header("content-type"), CONTENT_TYPE_JSON);
+            assert_eq!(
+                request.header(HEADER_SIGNATURE),
+                expected_signature(request.header(HEADER_TIMESTAMP), &request.body)
+            );
+            assert_eq!(request.body, received[0].body);
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (url, received) = receiver(usize::MAX).await;
+        let mut attempts = Vec::new();
+        let state = notifier(3).deliver(&url, &payload(), |attempt| attempts.push(attempt)).await;
+
+        assert_eq!(state, DeliveryState::Failed);
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts.iter().all(|attempt| attempt.status == Some(503) && attempt.error.is_some()));
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn refuses_private_receivers_by_default() {
+        let (url, received) = receiver(0).await;
+        let notifier = WebhookNotifier::new(SECRET, &WebhookConfig::default()).unwrap();
+        assert!(matches!(notifier.check(url.as_str()), Err(AppError::UrlNotAllowed(_))));
+
+        // Names pass the check and are refused when resolved
+        let named = Url::parse(&format!("http://localhost:{}/hook", url.port().unwrap())).unwrap();
+        let mut attempts = Vec::new();
+        let state = notifier.deliver(&named, &payload(), |attempt| attempts.push(attempt)).await;
+        assert_eq!(state, DeliveryState::Failed);
+        assert_eq!(attempts.len(), 1, "refused addresses are not retried");
+        assert!(received.lock().unwrap().is_empty());
+    }
+}
@@ -1,11 +1,22 @@
 use axum::response::{IntoResponse, Response};
 use axum::http::StatusCode;
+use std::fmt;
 use ort::Error as OrtError;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AppError {
     ImageProcessingError(String),
     ModelError(String),
+    ServiceUnavailable(String),
+    UnsupportedMediaType(String),
+    ImageTooLarge(String),
+    /// An `image_url` points to a host or address that may not be fetched
+    UrlNotAllowed(String),
+    /// Downloading an `image_url` failed
+    FetchFailed(String),
+    NotFound(String),
+    /// The resource is not in a state that allows the request, such as an unfinished job
+    Conflict(String),
 }
 
 impl IntoResponse for AppError {
@@ -13,16 +24,41 @@ impl IntoResponse for AppError {
         let (status, message) = match self {
             AppError::ImageProcessingError(msg) => (StatusCode::BAD_REQUEST, msg),
             AppError::ModelError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            AppError::ServiceUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            AppError::UnsupportedMediaType(msg) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, msg),
+            AppError::ImageTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, msg),
+            AppError::UrlNotAllowed(msg) => (StatusCode::FORBIDDEN, msg),
+            AppError::FetchFailed(msg) => (StatusCode::BAD_GATEWAY, msg),
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg),
         };
 
         (status, message).into_response()
     }
 } 
 
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::ImageProcessingError(msg)
+            | AppError::ModelError(msg)
+            | AppError::ServiceUnavailable(msg)
+            | AppError::UnsupportedMediaType(msg)
+            | AppError::ImageTooLarge(msg)
+            | AppError::UrlNotAllowed(msg)
+            | AppError::FetchFailed(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg) => f.write_str(msg),
+        }
+    }
+}
+
 pub struct ErrorMessages;
 
 impl ErrorMessages {
     pub const FAILED_TO_INITIALIZE_IMAGE_PROCESSOR: &'static str = "Failed to initialize image processor";
+    pub const FAILED_TO_INITIALIZE_IMAGE_FETCHER: &'static str = "Failed to initialize image fetcher";
+    pub const FAILED_TO_INITIALIZE_WEBHOOK_NOTIFIER: &'static str = "Failed to initialize webhook notifier";
 }
 
 impl From<OrtError> for AppError {
@@ -0,0 +1,188 @@
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use image::ImageFormat;
+use crate::application::config::{FetchConfig, JobConfig, ProcessorConfig, WebhookConfig};
+use crate::application::constants::input::BYTES_PER_MB;
+use super::constants::InfrastructureConstants;
+
+/// Builds the processor configuration from environment variables, keeping defaults for unset values
+pub fn processor_config_from_env() -> ProcessorConfig {
+    let mut config = ProcessorConfig::default();
+
+    if let Ok(models_dir) = env::var(InfrastructureConstants::ENV_MODELS_DIR) {
+        config.models_dir = models_dir;
+    }
+    if let Ok(models) = env::var(InfrastructureConstants::ENV_MODELS) {
+        config.models = parse_list(&models);
+    }
+    if let Ok(models) = env::var(InfrastructureConstants::ENV_SIGMOID_MODELS) {
+        config.sigmoid_models = parse_list(&models);
+    }
+    if let Ok(default_model) = env::var(InfrastructureConstants::ENV_DEFAULT_MODEL) {
+        config.default_model = default_model.trim().to_string();
+    }
+    if let Some(size) = parse_var(InfrastructureConstants::ENV_SESSION_POOL_SIZE) {
+        config.session_pool_size = size;
+    }
+    if let Some(threads) = parse_var(InfrastructureConstants::ENV_INTRA_OP_THREADS) {
+        config.intra_op_threads = threads;
+    }
+    if let Some(size) = parse_var(InfrastructureConstants::ENV_BATCH_MAX_SIZE) {
+        config.batch_max_size = size;
+    }
+    if let Some(window_ms) = parse_var(InfrastructureConstants::ENV_BATCH_WINDOW_MS) {
+        config.batch_window = Duration::from_millis(window_ms);
+    }
+    if let Some(threads) = parse_var(InfrastructureConstants::ENV_WORKER_THREADS) {
+        config.worker_threads = threads;
+    }
+    if let Some(capacity) = parse_var(InfrastructureConstants::ENV_QUEUE_CAPACITY) {
+        config.queue_capacity = capacity;
+    }
+    if let Some(timeout_secs) = parse_var(InfrastructureConstants::ENV_QUEUE_TIMEOUT_SECS) {
+        config.queue_timeout = Duration::from_secs(timeout_secs);
+    }
+    if let Some(compression) = parse_var(InfrastructureConstants::ENV_PNG_COMPRESSION) {
+        config.png.compression = compression;
+    }
+    if let Some(filter) = parse_var(InfrastructureConstants::ENV_PNG_FILTER) {
+        config.png.filter = filter;
+    }
+    if let Ok(formats) = env::var(InfrastructureConstants::ENV_INPUT_FORMATS) {
+        config.input_formats = parse_formats(&formats);
+    }
+    if let Some(width) = parse_var(InfrastructureConstants::ENV_MAX_IMAGE_WIDTH) {
+        config.decode_limits.max_width = width;
+    }
+    if let Some(height) = parse_var(InfrastructureConstants::ENV_MAX_IMAGE_HEIGHT) {
+        config.decode_limits.max_height = height;
+    }
+    if let Some(pixels) = parse_var(InfrastructureConstants::ENV_MAX_IMAGE_PIXELS) {
+        config.decode_limits.max_pixels = pixels;
+    }
+    if let Some(memory_mb) = parse_var::<u64>(InfrastructureConstants::ENV_MAX_DECODE_MEMORY_MB) {
+        config.decode_limits.max_alloc = memory_mb.saturating_mul(BYTES_PER_MB);
+    }
+
+    config
+}
+
+/// Builds the `image_url` fetch configuration from environment variables
+pub fn fetch_config_from_env() -> FetchConfig {
+    let mut config = FetchConfig::default();
+
+    if let Ok(hosts) = env::var(InfrastructureConstants::ENV_IMAGE_URL_ALLOWED_HOSTS) {
+        config.allowed_hosts = parse_list(&hosts);
+    }
+    if let Some(size_mb) = parse_var::<u64>(InfrastructureConstants::ENV_IMAGE_URL_MAX_SIZE_MB) {
+        config.max_size = size_mb.saturating_mul(BYTES_PER_MB);
+    }
+    if let Some(timeout_secs) = parse_var(InfrastructureConstants::ENV_IMAGE_URL_TIMEOUT_SECS) {
+        config.timeout = Duration::from_secs(timeout_secs);
+    }
+    if let Some(redirects) = parse_var(InfrastructureConstants::ENV_IMAGE_URL_MAX_REDIRECTS) {
+        config.max_redirects = redirects;
+    }
+    if let Some(allow) = parse_var(InfrastructureConstants::ENV_IMAGE_URL_ALLOW_PRIVATE) {
+        config.allow_private_addresses = allow;
+    }
+
+    config
+}
+
+/// Builds the background job configuration from environment variables
+pub fn job_config_from_env() -> JobConfig {
+    let mut config = JobConfig::default();
+
+    if let Some(ttl_secs) = parse_var(InfrastructureConstants::ENV_JOB_TTL_SECS) {
+        config.ttl = Duration::from_secs(ttl_secs);
+    }
+    if let Some(concurrency) = parse_var(InfrastructureConstants::ENV_JOB_CONCURRENCY) {
+        config.concurrency = concurrency;
+    }
+    if let Some(max_jobs) = parse_var(InfrastructureConstants::ENV_JOB_MAX_JOBS) {
+        config.max_jobs = max_jobs;
+    }
+    if let Some(result_mb) = parse_var::<u64>(InfrastructureConstants::ENV_JOB_MAX_RESULT_MB) {
+        config.max_result_bytes = result_mb.saturating_mul(BYTES_PER_MB);
+    }
+    if let Some(pending_mb) = parse_var::<u64>(InfrastructureConstants::ENV_JOB_MAX_PENDING_MB) {
+        config.max_pending_bytes = pending_mb.saturating_mul(BYTES_PER_MB);
+    }
+
+    config
+}
+
+/// Body limit of the job submission route, in bytes
+pub fn job_body_limit_from_env() -> usize {
+    let size_mb = parse_var::<u64>(InfrastructureConstants::ENV_JOB_MAX_BODY_SIZE_MB)
+        .unwrap_or(InfrastructureConstants::DEFAULT_JOB_MAX_BODY_SIZE_MB);
+    usize::try_from(size_mb.saturating_mul(BYTES_PER_MB)).unwrap_or(usize::MAX)
+}
+
+/// Builds the job callback configuration from environment variables
+pub fn webhook_config_from_env() -> WebhookConfig {
+    let mut config = WebhookConfig {
+        secret: env::var(InfrastructureConstants::ENV_WEBHOOK_SECRET).ok().filter(|secret| !secret.is_empty()),
+        ..WebhookConfig::default()
+    };
+
+    if let Ok(hosts) = env::var(InfrastructureConstants::ENV_WEBHOOK_ALLOWED_HOSTS) {
+        config.allowed_hosts = parse_list(&hosts);
+    }
+    if let Some(allow) = parse_var(InfrastructureConstants::ENV_WEBHOOK_ALLOW_PRIVATE) {
+        config.allow_private_addresses = allow;
+    }
+    if let Some(attempts) = parse_var(InfrastructureConstants::ENV_WEBHOOK_MAX_ATTEMPTS) {
+        config.max_attempts = attempts;
+    }
+    if let Some(backoff_ms) = parse_var(InfrastructureConstants::ENV_WEBHOOK_INITIAL_BACKOFF_MS) {
+        config.initial_backoff = Duration::from_millis(backoff_ms);
+    }
+    if let Some(backoff_secs) = parse_var(InfrastructureConstants::ENV_WEBHOOK_MAX_BACKOFF_SECS) {
+        config.max_backoff = Duration::from_secs(backoff_secs);
+    }
+    if let Some(timeout_secs) = parse_var(InfrastructureConstants::ENV_WEBHOOK_TIMEOUT_SECS) {
+        config.attempt_timeout = Duration::from_secs(timeout_secs);
+    }
+
+    config
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(InfrastructureConstants::LIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses format names or extensions such as `png,jpg,webp`, skipping formats this build cannot decode
+fn parse_formats(value: &str) -> Vec<ImageFormat> {
+    let mut formats = Vec::new();
+    for name in parse_list(value) {
+        match ImageFormat::from_extension(name.to_ascii_lowercase()) {
+            Some(format) if format.reading_enabled() => {
+                if !formats.contains(&format) {
+                    formats.push(format);
+                }
+            }
+            _ => tracing::warn!("Ignoring unsupported input format in {}: {}", InfrastructureConstants::ENV_INPUT_FORMATS, name),
+        }
+    }
+    formats
+}
+
+/// Reads and parses a variable, ignoring it with a warning when it is not a valid value
+fn parse_var<T: FromStr>(name: &str) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            tracing::warn!("Ignoring invalid value for {}: {}", name, value);
+            None
+        }
+    }
+}
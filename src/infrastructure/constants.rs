@@ -3,6 +3,51 @@ pub struct InfrastructureConstants;
 impl InfrastructureConstants {
     pub const DEFAULT_PORT: u16 = 8000;
     pub const MAX_BODY_SIZE: usize = 10 * 1024 * 1024; // 10MB
-    pub const PATH_REMOVE_BACKGROUND: &'static str = "/api/rem-bg";
-    pub const PATH_BATCH_REMOVE_BACKGROUND: &'static str = "/api/batch-rem-bg";
-}
\ No newline at end of file
+    /// Body limit of job submissions, which carry whole batches, in MiB
+    pub const DEFAULT_JOB_MAX_BODY_SIZE_MB: u64 = 100;
+    pub const PATH_REMOVE_BACKGROUND: &str = "/api/rem-bg";
+    pub const PATH_REMOVE_BACKGROUND_JSON: &str = "/api/rem-bg/json";
+    pub const PATH_BATCH_REMOVE_BACKGROUND: &str = "/api/batch-rem-bg";
+    pub const PATH_JOBS: &str = "/api/jobs";
+    pub const PATH_JOB: &str = "/api/jobs/{id}";
+    pub const PATH_JOB_RESULT: &str = "/api/jobs/{id}/result";
+
+    // Environment variables
+    pub const ENV_MODELS_DIR: &str = "MODELS_DIR";
+    pub const ENV_MODELS: &str = "MODELS";
+    pub const ENV_DEFAULT_MODEL: &str = "DEFAULT_MODEL";
+    pub const ENV_SIGMOID_MODELS: &str = "SIGMOID_MODELS";
+    pub const ENV_SESSION_POOL_SIZE: &str = "SESSION_POOL_SIZE";
+    pub const ENV_INTRA_OP_THREADS: &str = "INTRA_OP_THREADS";
+    pub const ENV_BATCH_MAX_SIZE: &str = "BATCH_MAX_SIZE";
+    pub const ENV_BATCH_WINDOW_MS: &str = "BATCH_WINDOW_MS";
+    pub const ENV_WORKER_THREADS: &str = "WORKER_THREADS";
+    pub const ENV_QUEUE_CAPACITY: &str = "QUEUE_CAPACITY";
+    pub const ENV_QUEUE_TIMEOUT_SECS: &str = "QUEUE_TIMEOUT_SECS";
+    pub const ENV_PNG_COMPRESSION: &str = "PNG_COMPRESSION";
+    pub const ENV_PNG_FILTER: &str = "PNG_FILTER";
+    pub const ENV_INPUT_FORMATS: &str = "INPUT_FORMATS";
+    pub const ENV_MAX_IMAGE_WIDTH: &str = "MAX_IMAGE_WIDTH";
+    pub const ENV_MAX_IMAGE_HEIGHT: &str = "MAX_IMAGE_HEIGHT";
+    pub const ENV_MAX_IMAGE_PIXELS: &str = "MAX_IMAGE_PIXELS";
+    pub const ENV_MAX_DECODE_MEMORY_MB: &str = "MAX_DECODE_MEMORY_MB";
+    pub const ENV_IMAGE_URL_ALLOWED_HOSTS: &str = "IMAGE_URL_ALLOWED_HOSTS";
+    pub const ENV_IMAGE_URL_MAX_SIZE_MB: &str = "IMAGE_URL_MAX_SIZE_MB";
+    pub const ENV_IMAGE_URL_TIMEOUT_SECS: &str = "IMAGE_URL_TIMEOUT_SECS";
+    pub const ENV_IMAGE_URL_MAX_REDIRECTS: &str = "IMAGE_URL_MAX_REDIRECTS";
+    pub const ENV_IMAGE_URL_ALLOW_PRIVATE: &str = "IMAGE_URL_ALLOW_PRIVATE";
+    pub const ENV_JOB_TTL_SECS: &str = "JOB_TTL_SECS";
+    pub const ENV_JOB_CONCURRENCY: &str = "JOB_CONCURRENCY";
+    pub const ENV_JOB_MAX_JOBS: &str = "JOB_MAX_JOBS";
+    pub const ENV_JOB_MAX_RESULT_MB: &str = "JOB_MAX_RESULT_MB";
+    pub const ENV_JOB_MAX_PENDING_MB: &str = "JOB_MAX_PENDING_MB";
+    pub const ENV_JOB_MAX_BODY_SIZE_MB: &str = "JOB_MAX_BODY_SIZE_MB";
+    pub const ENV_WEBHOOK_SECRET: &str = "WEBHOOK_SECRET";
+    pub const ENV_WEBHOOK_ALLOWED_HOSTS: &str = "WEBHOOK_ALLOWED_HOSTS";
+    pub const ENV_WEBHOOK_ALLOW_PRIVATE: &str = "WEBHOOK_ALLOW_PRIVATE";
+    pub const ENV_WEBHOOK_MAX_ATTEMPTS: &str = "WEBHOOK_MAX_ATTEMPTS";
+    pub const ENV_WEBHOOK_INITIAL_BACKOFF_MS: &str = "WEBHOOK_INITIAL_BACKOFF_MS";
+    pub const ENV_WEBHOOK_MAX_BACKOFF_SECS: &str = "WEBHOOK_MAX_BACKOFF_SECS";
+    pub const ENV_WEBHOOK_TIMEOUT_SECS: &str = "WEBHOOK_TIMEOUT_SECS";
+    pub const LIST_SEPARATOR: char = ',';
+}
@@ -1,2 +1,3 @@
 pub mod server; 
-pub mod constants;
\ No newline at end of file
+pub mod constants;
+pub mod config;
@@ -1,24 +1,68 @@
 use axum::{
-    routing::post,
+    routing::{get, post},
     Router,
     extract::DefaultBodyLimit,
 };
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
+use crate::application::image_fetcher::ImageFetcher;
 use crate::application::image_processor::ImageProcessor;
+use crate::application::jobs::JobManager;
+use crate::application::webhooks::WebhookNotifier;
 use crate::presentation::handlers;
+use crate::presentation::state::AppState;
 use crate::domain::ErrorMessages;
 use super::constants::InfrastructureConstants;
+use super::config::{
+    fetch_config_from_env, job_body_limit_from_env, job_config_from_env, processor_config_from_env, webhook_config_from_env,
+};
 
 pub async fn create_app() -> Router {
+    let config = processor_config_from_env();
     let image_processor = Arc::new(
-        ImageProcessor::new().expect(ErrorMessages::FAILED_TO_INITIALIZE_IMAGE_PROCESSOR)
+        ImageProcessor::new(&config).expect(ErrorMessages::FAILED_TO_INITIALIZE_IMAGE_PROCESSOR)
+    );
+    tracing::info!("Loaded models: {}", image_processor.model_names().join(", "));
+    tracing::info!("Accepted input formats: {:?}", image_processor.input_formats());
+
+    let fetch_config = fetch_config_from_env();
+    let image_fetcher = Arc::new(
+        ImageFetcher::new(&fetch_config).expect(ErrorMessages::FAILED_TO_INITIALIZE_IMAGE_FETCHER)
     );
+    if fetch_config.allowed_hosts.is_empty() {
+        tracing::info!("Image URLs allowed from any host");
+    } else {
+        tracing::info!("Image URLs allowed from: {}", fetch_config.allowed_hosts.join(", "));
+    }
+
+    let webhook_config = webhook_config_from_env();
+    let notifier = match &webhook_config.secret {
+        Some(secret) => Some(Arc::new(
+            WebhookNotifier::new(secret, &webhook_config).expect(ErrorMessages::FAILED_TO_INITIALIZE_WEBHOOK_NOTIFIER)
+        )),
+        None => {
+            tracing::info!("Job callbacks disabled, {} is not set", InfrastructureConstants::ENV_WEBHOOK_SECRET);
+            None
+        }
+    };
+
+    let job_config = job_config_from_env();
+    let job_manager = Arc::new(JobManager::new(Arc::clone(&image_processor), notifier, &job_config));
+    job_manager.spawn_cleanup();
+    tracing::info!("Job results kept for {:?}, {} images processed at a time", job_config.ttl, job_config.concurrency);
+    let job_body_limit = job_body_limit_from_env();
+
+    let state = AppState { processor: image_processor, fetcher: image_fetcher, jobs: job_manager };
 
     Router::new()
         .route(InfrastructureConstants::PATH_REMOVE_BACKGROUND, post(handlers::remove_background))
+        .route(InfrastructureConstants::PATH_REMOVE_BACKGROUND_JSON, post(handlers::remove_background_json))
         .route(InfrastructureConstants::PATH_BATCH_REMOVE_BACKGROUND, post(handlers::batch_remove_background))
+        // Overrides the default limit below for job submissions
+        .route(InfrastructureConstants::PATH_JOBS, post(handlers::create_job).layer(DefaultBodyLimit::max(job_body_limit)))
+        .route(InfrastructureConstants::PATH_JOB, get(handlers::job_status))
+        .route(InfrastructureConstants::PATH_JOB_RESULT, get(handlers::job_result))
         .layer(CorsLayer::permissive())
         .layer(DefaultBodyLimit::max(InfrastructureConstants::MAX_BODY_SIZE))
-        .with_state(image_processor)
+        .with_state(state)
 }
\ No newline at end of file
@@ -2,23 +2,98 @@ pub struct PresentationConstants;
 
 impl PresentationConstants {
     // Field names for multipart form data
-    pub const FIELD_IMAGE: &'static str = "image";
-    pub const FIELD_IMAGES: &'static str = "images";
+    pub const FIELD_IMAGE: &str = "image";
+    pub const FIELD_IMAGES: &str = "images";
+    pub const FIELD_IMAGE_URL: &str = "image_url";
+    pub const FIELD_CALLBACK_URL: &str = "callback_url";
+    pub const FIELD_CALLBACK_INCLUDE_IMAGES: &str = "callback_include_images";
+
+    // Processing option names, accepted as multipart fields or query parameters
+    pub const FIELD_MODEL: &str = "model";
+    pub const FIELD_HIGH_RES: &str = "high_res";
+    pub const FIELD_UPSAMPLING: &str = "upsampling";
+    pub const FIELD_ALPHA_MATTING: &str = "alpha_matting";
+    pub const FIELD_FOREGROUND_THRESHOLD: &str = "foreground_threshold";
+    pub const FIELD_BACKGROUND_THRESHOLD: &str = "background_threshold";
+    pub const FIELD_ERODE_SIZE: &str = "erode_size";
+    pub const FIELD_DECONTAMINATE: &str = "decontaminate";
+    pub const FIELD_OUTPUT: &str = "output";
+    pub const FIELD_BACKGROUND_COLOR: &str = "bg_color";
+    pub const FIELD_BACKGROUND_GRADIENT: &str = "bg_gradient";
+    pub const FIELD_BACKGROUND_IMAGE: &str = "bg_image";
+    pub const FIELD_BACKGROUND_FIT: &str = "bg_fit";
+    pub const FIELD_BACKGROUND_BLUR: &str = "bg_blur";
+    pub const FIELD_INPUT_ALPHA: &str = "input_alpha";
+    pub const FIELD_COLOR_PROFILE: &str = "color_profile";
+    pub const FIELD_KEEP_METADATA: &str = "keep_metadata";
+    pub const FIELD_CROP: &str = "crop";
+    pub const FIELD_CROP_PADDING: &str = "crop_padding";
+    pub const FIELD_CROP_ASPECT: &str = "crop_aspect";
+    pub const FIELD_CROP_THRESHOLD: &str = "crop_threshold";
+    pub const FIELD_FORMAT: &str = "format";
+    pub const FIELD_QUALITY: &str = "quality";
+    pub const FIELD_LOSSLESS: &str = "lossless";
+    pub const FIELD_PNG_COMPRESSION: &str = "png_compression";
+    pub const FIELD_PNG_FILTER: &str = "png_filter";
+    pub const FIELD_PNG_PALETTE: &str = "png_palette";
+
+    // Option values
+    pub const UPSAMPLING_BILINEAR: &str = "bilinear";
+    pub const UPSAMPLING_GUIDED: &str = "guided";
+    pub const OUTPUT_CUTOUT: &str = "cutout";
+    pub const OUTPUT_MASK: &str = "mask";
+    pub const OUTPUT_RAW_MASK: &str = "raw_mask";
+    pub const FIT_COVER: &str = "cover";
+    pub const FIT_CONTAIN: &str = "contain";
+    pub const DEFAULT_GRADIENT_ANGLE: f32 = 90.0;
+    pub const INPUT_ALPHA_MULTIPLY: &str = "multiply";
+    pub const INPUT_ALPHA_MIN: &str = "min";
+    pub const INPUT_ALPHA_IGNORE: &str = "ignore";
+    pub const COLOR_PROFILE_KEEP: &str = "keep";
+    pub const COLOR_PROFILE_SRGB: &str = "srgb";
+    pub const COLOR_PROFILE_STRIP: &str = "strip";
+    pub const CROP_ASPECT_SQUARE: &str = "square";
+    pub const PADDING_SUFFIX_PERCENT: &str = "%";
+    pub const PADDING_SUFFIX_PIXELS: &str = "px";
+    pub const FORMAT_PNG: &str = "png";
+    pub const FORMAT_WEBP: &str = "webp";
+    pub const FORMAT_JPEG: &str = "jpeg";
+    pub const FORMAT_JPG: &str = "jpg";
+    pub const FORMAT_TIFF: &str = "tiff";
+    pub const FORMAT_TIF: &str = "tif";
+
+    // JSON image payloads
+    pub const DATA_URL_PREFIX: &str = "data:";
+    pub const DATA_URL_BASE64: &str = "base64";
+
+    // Jobs
+    pub const JOBS_URL: &str = "/api/jobs";
+    pub const JOB_RESULT_SUFFIX: &str = "/result";
 
     // Content types
-    pub const CONTENT_TYPE_PNG: &'static str = "image/png";
-    pub const CONTENT_TYPE_JPEG: &'static str = "image/jpeg";
-    pub const CONTENT_TYPE_JPG: &'static str = "image/jpg";
+    pub const CONTENT_TYPE_PNG: &str = "image/png";
+    pub const CONTENT_TYPE_JPEG: &str = "image/jpeg";
+    pub const CONTENT_TYPE_JPG: &str = "image/jpg";
+    pub const CONTENT_TYPE_WEBP: &str = "image/webp";
+    pub const CONTENT_TYPE_TIFF: &str = "image/tiff";
 
     // Response headers
-    pub const HEADER_CONTENT_TYPE_VALUE: &'static str = "image/png";
-    pub const HEADER_CONTENT_TYPE_ZIP: &'static str = "application/zip";
+    pub const HEADER_CONTENT_TYPE_ZIP: &str = "application/zip";
+    pub const HEADER_DETECTED_FORMAT: &str = "x-detected-format";
 
     // Error messages
-    pub const ERROR_UNSUPPORTED_IMAGE_FORMAT: &'static str = "Unsupported image format. Only PNG and JPEG/JPG are supported";
-    pub const ERROR_NO_IMAGE_FOUND: &'static str = "No image file found";
-    pub const ERROR_NO_IMAGES_PROCESSED: &'static str = "No images were successfully processed";
-    pub const ERROR_ZIP_CREATE: &'static str = "Failed to create zip file";
-    pub const ERROR_ZIP_WRITE: &'static str = "Failed to write to zip file";
-    pub const ERROR_ZIP_FINALIZE: &'static str = "Failed to finalize zip file";
+    pub const ERROR_UNSUPPORTED_IMAGE_FORMAT: &str = "Unsupported image format";
+    pub const ERROR_UNRECOGNIZED_IMAGE_FORMAT: &str = "Unrecognized image format";
+    pub const ERROR_FORMAT_MISMATCH: &str = "Content type does not match the image data";
+    pub const ERROR_NO_IMAGE_FOUND: &str = "No image file found";
+    pub const ERROR_IMAGE_AND_URL: &str = "Send either an image or an image_url, not both";
+    pub const ERROR_NO_IMAGES_PROCESSED: &str = "No images were successfully processed";
+    pub const ERROR_ZIP_CREATE: &str = "Failed to create zip file";
+    pub const ERROR_ZIP_WRITE: &str = "Failed to write to zip file";
+    pub const ERROR_ZIP_FINALIZE: &str = "Failed to finalize zip file";
+    pub const ERROR_INVALID_OPTION: &str = "Invalid value for option";
+    pub const ERROR_JOB_NOT_FOUND: &str = "Job not found";
+    pub const ERROR_INVALID_JSON: &str = "Invalid JSON request";
+    pub const ERROR_INVALID_DATA_URL: &str = "Data URL must be base64 encoded";
+    pub const ERROR_INVALID_BASE64: &str = "Invalid base64 image data";
 }
\ No newline at end of file
@@ -1,107 +1,209 @@
 use axum::{
-    extract::{State, Multipart},
-    response::Response,
-    http::{header, StatusCode},
+    extract::{State, Multipart, Path, Query},
+    extract::multipart::Field,
+    extract::rejection::JsonRejection,
+    response::{IntoResponse, Response},
+    Json,
+    http::{header, HeaderMap, StatusCode},
 };
+use std::collections::HashMap;
 use std::sync::Arc;
+use bytes::Bytes;
+use image::ImageFormat;
+use crate::application::image_fetcher::ImageFetcher;
 use crate::application::image_processor::ImageProcessor;
+use crate::application::jobs::JobManager;
+use crate::application::options::{OutputFormat, ProcessingOptions};
+use crate::application::webhooks::Callback;
 use crate::domain::AppError;
 use crate::presentation::constants::PresentationConstants;
+use crate::presentation::json::{
+    decode_image, option_value, JobCreatedResponse, JobStatusResponse, RemoveBackgroundRequest, RemoveBackgroundResponse,
+};
+use crate::presentation::options::{apply_option, content_type, file_extension, options_from_request, parse_bool};
 use tracing;
+use tokio::sync::Semaphore;
 use tokio::task;
 use uuid::Uuid;
 use std::io::Write;
 
 pub async fn remove_background(
     State(processor): State<Arc<ImageProcessor>>,
+    State(fetcher): State<Arc<ImageFetcher>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     mut multipart: Multipart,
 ) -> Result<Response, AppError> {
     let start_time = std::time::Instant::now();
     tracing::info!("Processing background removal request");
-    
+
+    let mut options = options_from_request(&headers, &query)?;
+    let mut image_data = None;
+    let mut image_url = None;
+
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         tracing::error!("Failed to process multipart form: {}", e);
         AppError::ImageProcessingError(e.to_string())
     })? {
         if field.name() == Some(PresentationConstants::FIELD_IMAGE) {
-            if let Some(content_type) = field.content_type() {
-                if content_type != PresentationConstants::CONTENT_TYPE_PNG && 
-                   content_type != PresentationConstants::CONTENT_TYPE_JPEG && 
-                   content_type != PresentationConstants::CONTENT_TYPE_JPG {
-                    tracing::error!("Unsupported image format: {}", content_type);
-                    return Err(AppError::ImageProcessingError(
-                        PresentationConstants::ERROR_UNSUPPORTED_IMAGE_FORMAT.to_string()
-                    ));
-                }
+            if image_data.is_some() {
+                continue;
             }
 
+            let content_type = field.content_type().map(str::to_string);
             let data = field.bytes().await.map_err(|e| {
                 tracing::error!("Failed to read image data: {}", e);
                 AppError::ImageProcessingError(e.to_string())
             })?;
+
+            let detected = detect_format(&processor, content_type.as_deref(), &data).inspect_err(|e| {
+                tracing::error!("Rejected image: {:?}", e);
+            })?;
+            tracing::info!("Detected input format: {}", detected.to_mime_type());
+            image_data = Some((data, detected));
+        } else if field.name() == Some(PresentationConstants::FIELD_IMAGE_URL) {
+            let url = field.text().await.map_err(|e| {
+                tracing::error!("Failed to read image URL: {}", e);
+                AppError::ImageProcessingError(e.to_string())
+            })?;
+            image_url = Some(url).filter(|url| !url.trim().is_empty());
+        } else {
+            read_option_field(field, &mut options).await?;
+        }
+    }
+
+    let (data, detected) = match (image_data, image_url) {
+        (Some(image), None) => image,
+        (None, Some(url)) => fetch_image(&processor, &fetcher, &url).await?,
+        (Some(_), Some(_)) => {
+            return Err(AppError::ImageProcessingError(PresentationConstants::ERROR_IMAGE_AND_URL.to_string()));
+        }
+        (None, None) => {
+            tracing::error!("No image found in request");
+            return Err(AppError::ImageProcessingError(PresentationConstants::ERROR_NO_IMAGE_FOUND.to_string()));
+        }
+    };
             
-            match processor.remove_background(&data).await {
-                Ok(result) => {
-                    tracing::info!("Success - took {:.2?}", start_time.elapsed());
-                    return Ok(Response::builder()
-                        .status(StatusCode::OK)
-                        .header(header::CONTENT_TYPE, PresentationConstants::HEADER_CONTENT_TYPE_VALUE)
-                        .body(axum::body::Body::from(result))
-                        .unwrap());
-                }
-                Err(e) => {
-                    tracing::error!("Failed after {:.2?}: {:?}", start_time.elapsed(), e);
-                    return Err(e);
-                }
-            }
+    let format = options.output_format();
+    match processor.remove_background(data, options).await {
+        Ok(result) => {
+            tracing::info!("Success - took {:.2?}", start_time.elapsed());
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, content_type(format))
+                .header(PresentationConstants::HEADER_DETECTED_FORMAT, detected.to_mime_type())
+                .body(axum::body::Body::from(result.data))
+                .unwrap())
+        }
+        Err(e) => {
+            tracing::error!("Failed after {:.2?}: {:?}", start_time.elapsed(), e);
+            Err(e)
         }
     }
-    
-    tracing::error!("No image found in request");
-    Err(AppError::ImageProcessingError(PresentationConstants::ERROR_NO_IMAGE_FOUND.to_string()))
 }
 
-pub async fn batch_remove_background(
+/// JSON variant of `remove_background` for clients that cannot build multipart bodies: the image
+/// and result travel as base64, and the response reports the output size and stage timings
+pub async fn remove_background_json(
     State(processor): State<Arc<ImageProcessor>>,
-    mut multipart: Multipart,
+    State(fetcher): State<Arc<ImageFetcher>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    payload: Result<Json<RemoveBackgroundRequest>, JsonRejection>,
 ) -> Result<Response, AppError> {
     let start_time = std::time::Instant::now();
-    tracing::info!("Processing batch background removal request");
-    
-    let mut processed_images = Vec::new();
-    let mut tasks = Vec::new();
+    tracing::info!("Processing JSON background removal request");
 
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        tracing::error!("Failed to process multipart form: {}", e);
-        AppError::ImageProcessingError(e.to_string())
-    })? {
-        if field.name() == Some(PresentationConstants::FIELD_IMAGES) {
-            if let Some(content_type) = field.content_type() {
-                if content_type != PresentationConstants::CONTENT_TYPE_PNG && 
-                   content_type != PresentationConstants::CONTENT_TYPE_JPEG && 
-                   content_type != PresentationConstants::CONTENT_TYPE_JPG {
-                    tracing::error!("Unsupported image format: {}", content_type);
-                    continue;
-                }
-            }
+    let Json(request) = payload.map_err(|e| {
+        tracing::error!("Failed to parse JSON request: {}", e);
+        AppError::ImageProcessingError(format!("{}: {}", PresentationConstants::ERROR_INVALID_JSON, e.body_text()))
+    })?;
 
-            let data = field.bytes().await.map_err(|e| {
-                tracing::error!("Failed to read image data: {}", e);
-                AppError::ImageProcessingError(e.to_string())
+    let mut options = options_from_request(&headers, &query)?;
+    for (name, value) in &request.options {
+        let Some(value) = option_value(name, value)? else {
+            continue;
+        };
+        if name == PresentationConstants::FIELD_BACKGROUND_IMAGE {
+            let (data, _) = decode_image(&value)?;
+            options.background.image = (!data.is_empty()).then_some(data);
+        } else if !apply_option(&mut options, name, &value)? {
+            tracing::debug!("Ignoring unknown option: {}", name);
+        }
+    }
+
+    let image = request.image.filter(|image| !image.trim().is_empty());
+    let image_url = request.image_url.filter(|url| !url.trim().is_empty());
+    let (data, detected) = match (image, image_url) {
+        (Some(image), None) => {
+            let (data, declared) = decode_image(&image)?;
+            let detected = detect_format(&processor, declared.as_deref(), &data).inspect_err(|e| {
+                tracing::error!("Rejected image: {:?}", e);
             })?;
+            (data, detected)
+        }
+        (None, Some(url)) => fetch_image(&processor, &fetcher, &url).await?,
+        (Some(_), Some(_)) => {
+            return Err(AppError::ImageProcessingError(PresentationConstants::ERROR_IMAGE_AND_URL.to_string()));
+        }
+        (None, None) => {
+            tracing::error!("No image found in request");
+            return Err(AppError::ImageProcessingError(PresentationConstants::ERROR_NO_IMAGE_FOUND.to_string()));
+        }
+    };
+    tracing::info!("Detected input format: {}", detected.to_mime_type());
 
-            let processor = Arc::clone(&processor);
-            let task = task::spawn(async move {
-                let result = processor.remove_background(&data).await?;
-                Ok::<_, AppError>(result)
-            });
-            tasks.push(task);
+    let format = options.output_format();
+    match processor.remove_background(data, options).await {
+        Ok(result) => {
+            tracing::info!("Success - took {:.2?}", start_time.elapsed());
+            let response = RemoveBackgroundResponse::new(&result, content_type(format));
+            Ok(([(PresentationConstants::HEADER_DETECTED_FORMAT, detected.to_mime_type())], Json(response)).into_response())
+        }
+        Err(e) => {
+            tracing::error!("Failed after {:.2?}: {:?}", start_time.elapsed(), e);
+            Err(e)
         }
     }
+}
+
+pub async fn batch_remove_background(
+    State(processor): State<Arc<ImageProcessor>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Result<Response, AppError> {
+    let start_time = std::time::Instant::now();
+    tracing::info!("Processing batch background removal request");
+
+    let (images, options, _) = read_batch_form(&processor, &headers, &query, multipart).await?;
+    let mut processed_images = Vec::new();
+    let mut tasks = Vec::new();
+    // Feeding the batch in at the workers' pace keeps its later images from timing out in the
+    // processing queue behind its earlier ones
+    let slots = Arc::new(Semaphore::new(processor.worker_threads().max(1)));
+
+    for data in images {
+        let processor = Arc::clone(&processor);
+        let options = options.clone();
+        let slots = Arc::clone(&slots);
+        let task = task::spawn(async move {
+            // The semaphore is never closed
+            let _slot = slots.acquire_owned().await.map_err(|e| AppError::ServiceUnavailable(e.to_string()))?;
+            let result = processor.remove_background(data, options).await?;
+            Ok::<_, AppError>(result)
+        });
+        tasks.push(task);
+    }
 
     for task in tasks {
         match task.await {
-            Ok(Ok(result)) => processed_images.push(result),
+            Ok(Ok(result)) => processed_images.push(result.data),
+            // A full queue says nothing about the image, so the batch is retried rather than cut short
+            Ok(Err(e @ AppError::ServiceUnavailable(_))) => {
+                tracing::error!("Batch stopped, processing queue is full: {:?}", e);
+                return Err(e);
+            }
             Ok(Err(e)) => {
                 tracing::error!("Failed to process image: {:?}", e);
                 continue;
@@ -118,16 +220,120 @@ pub async fn batch_remove_background(
         return Err(AppError::ImageProcessingError(PresentationConstants::ERROR_NO_IMAGES_PROCESSED.to_string()));
     }
 
-    // Create a zip file containing all processed images
+    let zip_buffer = zip_images(
+        processed_images.iter().enumerate().map(|(index, data)| (index, data.as_slice())),
+        options.output_format(),
+    )?;
+
+    tracing::info!("Batch processing completed - took {:.2?}", start_time.elapsed());
+    Ok(zip_response(zip_buffer, &Uuid::new_v4().to_string()))
+}
+
+/// Starts a background job over the same form as the batch endpoint and returns its id at once.
+/// A `callback_url` field asks for the results to be posted there when the job finishes.
+pub async fn create_job(
+    State(processor): State<Arc<ImageProcessor>>,
+    State(jobs): State<Arc<JobManager>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Result<Response, AppError> {
+    let (images, options, callback) = read_batch_form(&processor, &headers, &query, multipart).await?;
+    if images.is_empty() {
+        tracing::error!("No image found in request");
+        return Err(AppError::ImageProcessingError(PresentationConstants::ERROR_NO_IMAGE_FOUND.to_string()));
+    }
+
+    let count = images.len();
+    let id = jobs.submit(images, options, callback)?;
+    tracing::info!("Created job {} with {} images", id, count);
+
+    let response = JobCreatedResponse::new(id, count);
+    Ok((StatusCode::ACCEPTED, [(header::LOCATION, response.status_url.clone())], Json(response)).into_response())
+}
+
+pub async fn job_status(
+    State(jobs): State<Arc<JobManager>>,
+    Path(id): Path<String>,
+) -> Result<Json<JobStatusResponse>, AppError> {
+    let status = jobs.status(&id).ok_or_else(|| {
+        AppError::NotFound(format!("{}: {}", PresentationConstants::ERROR_JOB_NOT_FOUND, id))
+    })?;
+    Ok(Json(JobStatusResponse::new(id, &status)))
+}
+
+/// Zip of the successful images of a finished job, named by their position in the submission
+pub async fn job_result(
+    State(jobs): State<Arc<JobManager>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let output = jobs.output(&id)?;
+    let zip_buffer = zip_images(
+        output.images.iter().map(|(index, data)| (*index, data.as_ref())),
+        output.format,
+    )?;
+    Ok(zip_response(zip_buffer, &id))
+}
+
+/// Reads the images, options and job callback of a batch form. Options may follow the images in
+/// the form, so they only apply once every field is read; images that fail format detection are
+/// skipped.
+async fn read_batch_form(
+    processor: &ImageProcessor,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+    mut multipart: Multipart,
+) -> Result<(Vec<Bytes>, ProcessingOptions, Option<Callback>), AppError> {
+    let mut options = options_from_request(headers, query)?;
+    let mut images = Vec::new();
+    let mut callback_url = None;
+    let mut include_images = false;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        tracing::error!("Failed to process multipart form: {}", e);
+        AppError::ImageProcessingError(e.to_string())
+    })? {
+        if field.name() == Some(PresentationConstants::FIELD_IMAGES) {
+            let content_type = field.content_type().map(str::to_string);
+            let data = field.bytes().await.map_err(|e| {
+                tracing::error!("Failed to read image data: {}", e);
+                AppError::ImageProcessingError(e.to_string())
+            })?;
+
+            match detect_format(processor, content_type.as_deref(), &data) {
+                Ok(detected) => {
+                    tracing::info!("Detected input format: {}", detected.to_mime_type());
+                    images.push(data);
+                }
+                Err(e) => tracing::error!("Skipping image: {:?}", e),
+            }
+        } else if field.name() == Some(PresentationConstants::FIELD_CALLBACK_URL) {
+            let url = read_text_field(field).await?;
+            callback_url = (!url.trim().is_empty()).then_some(url);
+        } else if field.name() == Some(PresentationConstants::FIELD_CALLBACK_INCLUDE_IMAGES) {
+            let value = read_text_field(field).await?;
+            include_images = parse_bool(PresentationConstants::FIELD_CALLBACK_INCLUDE_IMAGES, value.trim())?;
+        } else {
+            read_option_field(field, &mut options).await?;
+        }
+    }
+
+    let callback = callback_url.map(|url| Callback { url, include_images });
+    Ok((images, options, callback))
+}
+
+/// Stores `(index, data)` images as `processed_image_<index + 1>.<ext>`
+fn zip_images<'a>(images: impl Iterator<Item = (usize, &'a [u8])>, format: OutputFormat) -> Result<Vec<u8>, AppError> {
     let mut zip_buffer = Vec::new();
     {
         let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut zip_buffer));
-        let options = zip::write::FileOptions::default()
+        let file_options = zip::write::FileOptions::default()
             .compression_method(zip::CompressionMethod::Stored);
 
-        for (index, image_data) in processed_images.iter().enumerate() {
-            let filename = format!("processed_image_{}.png", index + 1);
-            zip.start_file(&filename, options).map_err(|e| {
+        let extension = file_extension(format);
+        for (index, image_data) in images {
+            let filename = format!("processed_image_{}.{}", index + 1, extension);
+            zip.start_file(&filename, file_options).map_err(|e| {
                 AppError::ImageProcessingError(format!("{}: {}", PresentationConstants::ERROR_ZIP_CREATE, e))
             })?;
             zip.write_all(image_data).map_err(|e| {
@@ -138,16 +344,89 @@ pub async fn batch_remove_background(
             AppError::ImageProcessingError(format!("{}: {}", PresentationConstants::ERROR_ZIP_FINALIZE, e))
         })?;
     }
+    Ok(zip_buffer)
+}
 
-    tracing::info!("Batch processing completed - took {:.2?}", start_time.elapsed());
-    
-    Ok(Response::builder()
+fn zip_response(zip_buffer: Vec<u8>, name: &str) -> Response {
+    Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, PresentationConstants::HEADER_CONTENT_TYPE_ZIP)
         .header(
             header::CONTENT_DISPOSITION,
-            format!("attachment; filename=\"processed_images_{}.zip\"", Uuid::new_v4())
+            format!("attachment; filename=\"processed_images_{}.zip\"", name)
         )
         .body(axum::body::Body::from(zip_buffer))
-        .unwrap())
-}
\ No newline at end of file
+        .unwrap()
+}
+
+/// Downloads an `image_url`; the format is detected from the data alone, since servers often
+/// label images loosely
+async fn fetch_image(processor: &ImageProcessor, fetcher: &ImageFetcher, url: &str) -> Result<(Bytes, ImageFormat), AppError> {
+    tracing::info!("Fetching image from {}", url);
+    let data = fetcher.fetch(url).await.inspect_err(|e| {
+        tracing::error!("Failed to fetch image: {:?}", e);
+    })?;
+    let detected = detect_format(processor, None, &data).inspect_err(|e| {
+        tracing::error!("Rejected image: {:?}", e);
+    })?;
+    Ok((data, detected))
+}
+
+/// Detects the image format from its magic bytes. A declared image content type must agree with
+/// the data; generic or missing content types are ignored.
+fn detect_format(processor: &ImageProcessor, content_type: Option<&str>, data: &[u8]) -> Result<ImageFormat, AppError> {
+    let detected = image::guess_format(data).map_err(|_| {
+        AppError::UnsupportedMediaType(PresentationConstants::ERROR_UNRECOGNIZED_IMAGE_FORMAT.to_string())
+    })?;
+
+    if let Some(content_type) = content_type {
+        let declared = content_type.trim().to_ascii_lowercase();
+        let declared_format = if declared == PresentationConstants::CONTENT_TYPE_JPG {
+            Some(ImageFormat::Jpeg)
+        } else {
+            ImageFormat::from_mime_type(&declared)
+        };
+        if declared_format.is_some_and(|format| format != detected) {
+            return Err(AppError::UnsupportedMediaType(format!(
+                "{}: declared {}, detected {}",
+                PresentationConstants::ERROR_FORMAT_MISMATCH, declared, detected.to_mime_type()
+            )));
+        }
+    }
+
+    if !processor.accepts_input(detected) {
+        return Err(AppError::UnsupportedMediaType(format!(
+            "{}: {}", PresentationConstants::ERROR_UNSUPPORTED_IMAGE_FORMAT, detected.to_mime_type()
+        )));
+    }
+    Ok(detected)
+}
+
+async fn read_option_field(field: Field<'_>, options: &mut ProcessingOptions) -> Result<(), AppError> {
+    let Some(name) = field.name().map(str::to_string) else {
+        return Ok(());
+    };
+
+    if name == PresentationConstants::FIELD_BACKGROUND_IMAGE {
+        let data = field.bytes().await.map_err(|e| {
+            tracing::error!("Failed to read background image: {}", e);
+            AppError::ImageProcessingError(e.to_string())
+        })?;
+        options.background.image = (!data.is_empty()).then_some(data);
+        return Ok(());
+    }
+
+    let value = read_text_field(field).await?;
+    if !apply_option(options, &name, &value)? {
+        tracing::debug!("Ignoring unknown form field: {}", name);
+    }
+    Ok(())
+}
+
+async fn read_text_field(field: Field<'_>) -> Result<String, AppError> {
+    let name = field.name().unwrap_or_default().to_string();
+    field.text().await.map_err(|e| {
+        tracing::error!("Failed to read form field {}: {}", name, e);
+        AppError::ImageProcessingError(e.to_string())
+    })
+}
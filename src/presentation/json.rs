@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+use base64::alphabet;
+use base64::engine::{general_purpose, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::application::image_processor::{ProcessedImage, StageTimings};
+use crate::application::jobs::{ImageStatus, JobState, JobStatus};
+use crate::application::webhooks::{CallbackStatus, DeliveryAttempt};
+use crate::domain::AppError;
+use crate::presentation::constants::PresentationConstants;
+
+/// Accepts standard base64 with or without padding
+const LENIENT_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Body of the JSON background removal endpoint
+#[derive(Debug, Deserialize)]
+pub struct RemoveBackgroundRequest {
+    /// Base64 image data or a base64 data URL
+    #[serde(default)]
+    pub image: Option<String>,
+    /// URL the server downloads the image from, instead of `image`
+    #[serde(default)]
+    pub image_url: Option<String>,
+    /// Same options as the multipart endpoint; `bg_image` is base64 as well
+    #[serde(default)]
+    pub options: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RemoveBackgroundResponse {
+    /// Base64 encoded result
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    pub mime: &'static str,
+    pub timings: TimingsResponse,
+}
+
+impl RemoveBackgroundResponse {
+    pub fn new(processed: &ProcessedImage, mime: &'static str) -> Self {
+        Self {
+            image: general_purpose::STANDARD.encode(&processed.data),
+            width: processed.width,
+            height: processed.height,
+            mime,
+            timings: TimingsResponse::from(processed.timings),
+        }
+    }
+}
+
+/// Stage timings in milliseconds
+#[derive(Debug, Serialize)]
+pub struct TimingsResponse {
+    pub queue_ms: f64,
+    pub decode_ms: f64,
+    pub inference_ms: f64,
+    pub postprocess_ms: f64,
+    pub encode_ms: f64,
+    pub total_ms: f64,
+}
+
+impl From<StageTimings> for TimingsResponse {
+    fn from(timings: StageTimings) -> Self {
+        let ms = |duration: std::time::Duration| duration.as_secs_f64() * 1000.0;
+        Self {
+            queue_ms: ms(timings.queue),
+            decode_ms: ms(timings.decode),
+            inference_ms: ms(timings.inference),
+            postprocess_ms: ms(timings.postprocess),
+            encode_ms: ms(timings.encode),
+            total_ms: ms(timings.total),
+        }
+    }
+}
+
+/// Reply to a job submission
+#[derive(Debug, Serialize)]
+pub struct JobCreatedResponse {
+    pub id: String,
+    pub status: &'static str,
+    pub images: usize,
+    pub status_url: String,
+    pub result_url: String,
+}
+
+impl JobCreatedResponse {
+    pub fn new(id: String, images: usize) -> Self {
+        Self {
+            status_url: job_status_url(&id),
+            result_url: format!("{}{}", job_status_url(&id), PresentationConstants::JOB_RESULT_SUFFIX),
+            id,
+            status: JobState::Queued.name(),
+            images,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobStatusResponse {
+    pub id: String,
+    pub status: &'static str,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub images: Vec<JobImageResponse>,
+    /// Seconds until a finished job is removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback: Option<CallbackResponse>,
+}
+
+impl JobStatusResponse {
+    pub fn new(id: String, status: &JobStatus) -> Self {
+        let images: Vec<JobImageResponse> = status.images.iter().enumerate().map(JobImageResponse::new).collect();
+        Self {
+            id,
+            status: status.state.name(),
+            total: images.len(),
+            completed: status.images.iter().filter(|image| **image == ImageStatus::Completed).count(),
+            failed: status.images.iter().filter(|image| matches!(image, ImageStatus::Failed(_))).count(),
+            images,
+            expires_in_secs: status.expires_in.map(|expires_in| expires_in.as_secs()),
+            callback: status.callback.as_ref().map(CallbackResponse::new),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobImageResponse {
+    pub index: usize,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl JobImageResponse {
+    fn new((index, status): (usize, &ImageStatus)) -> Self {
+        let error = match status {
+            ImageStatus::Failed(error) => Some(error.clone()),
+            _ => None,
+        };
+        Self { index, status: status.name(), error }
+    }
+}
+
+/// Delivery log of a job callback
+#[derive(Debug, Serialize)]
+pub struct CallbackResponse {
+    pub url: String,
+    pub status: &'static str,
+    pub attempts: Vec<DeliveryAttemptResponse>,
+}
+
+impl CallbackResponse {
+    fn new(status: &CallbackStatus) -> Self {
+        Self {
+            url: status.url.clone(),
+            status: status.state.name(),
+            attempts: status.attempts.iter().map(DeliveryAttemptResponse::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeliveryAttemptResponse {
+    pub attempt: u32,
+    /// Unix time in seconds
+    pub sent_at: u64,
+    pub duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<&DeliveryAttempt> for DeliveryAttemptResponse {
+    fn from(attempt: &DeliveryAttempt) -> Self {
+        Self {
+            attempt: attempt.attempt,
+            sent_at: attempt.sent_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            duration_ms: attempt.duration.as_secs_f64() * 1000.0,
+            status_code: attempt.status,
+            error: attempt.error.clone(),
+        }
+    }
+}
+
+fn job_status_url(id: &str) -> String {
+    format!("{}/{}", PresentationConstants::JOBS_URL, id)
+}
+
+/// Decodes base64 image data, optionally wrapped in a `data:<mime>;base64,` URL. Returns the
+/// bytes and the media type declared by the data URL.
+pub fn decode_image(value: &str) -> Result<(Bytes, Option<String>), AppError> {
+    let (declared, payload) = match value.trim().strip_prefix(PresentationConstants::DATA_URL_PREFIX) {
+        Some(data_url) => {
+            let (metadata, payload) = data_url.split_once(',').ok_or_else(|| {
+                AppError::ImageProcessingError(PresentationConstants::ERROR_INVALID_DATA_URL.to_string())
+            })?;
+            let mut parameters = metadata.split(';').map(str::trim);
+            let media_type = parameters.next().filter(|media_type| !media_type.is_empty()).map(str::to_string);
+            if !parameters.any(|parameter| parameter.eq_ignore_ascii_case(PresentationConstants::DATA_URL_BASE64)) {
+                return Err(AppError::ImageProcessingError(PresentationConstants::ERROR_INVALID_DATA_URL.to_string()));
+            }
+            (media_type, payload)
+        }
+        None => (None, value),
+    };
+
+    // Line-wrapped base64 is common, so whitespace is ignored
+    let payload: Vec<u8> = payload.bytes().filter(|byte| !byte.is_ascii_whitespace()).collect();
+    let data = LENIENT_BASE64.decode(payload).map_err(|e| {
+        AppError::ImageProcessingError(format!("{}: {}", PresentationConstants::ERROR_INVALID_BASE64, e))
+    })?;
+    Ok((Bytes::from(data), declared))
+}
+
+/// Option values may be JSON strings, numbers or booleans; `null` leaves the option unset
+pub fn option_value(name: &str, value: &Value) -> Result<Option<String>, AppError> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(value) => Ok(Some(value.clone())),
+        Value::Number(value) => Ok(Some(value.to_string())),
+        Value::Bool(value) => Ok(Some(value.to_string())),
+        _ => Err(AppError::ImageProcessingError(format!(
+            "{} {}: expected a string, number or boolean", PresentationConstants::ERROR_INVALID_OPTION, name
+        ))),
+    }
+}
@@ -1,2 +1,5 @@
 pub mod handlers; 
-pub mod constants;
\ No newline at end of file
+pub mod constants;
+pub mod options;
+pub mod json;
+pub mod state;
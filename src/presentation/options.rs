@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use axum::http::{header, HeaderMap};
+use crate::application::constants::{compositing::MAX_BLUR_RADIUS, crop::*};
+use crate::application::options::{
+    BackgroundFit, ColorProfile, CropPadding, Gradient, InputAlpha, MaskUpsampling, OutputFormat, OutputMode, ProcessingOptions,
+};
+use crate::domain::AppError;
+use crate::presentation::constants::PresentationConstants;
+
+/// Builds processing options from the `Accept` header and query parameters; an explicit `format`
+/// wins over `Accept`, and multipart fields can override everything later. A negotiated format
+/// without alpha gives way to the best accepted one with alpha, or PNG, for transparent cutouts.
+pub fn options_from_request(headers: &HeaderMap, query: &HashMap<String, String>) -> Result<ProcessingOptions, AppError> {
+    let mut options = ProcessingOptions::default();
+    let accepted = formats_from_accept(headers);
+    if let Some(&format) = accepted.first() {
+        options.encoding.format = format;
+        options.encoding.alpha_fallback = Some(
+            accepted.into_iter().find(|format| format.supports_alpha()).unwrap_or_default()
+        );
+    }
+    for (name, value) in query {
+        apply_option(&mut options, name, value)?;
+    }
+    Ok(options)
+}
+
+/// Applies a single named option. Returns `false` when the name is not a processing option.
+pub fn apply_option(options: &mut ProcessingOptions, name: &str, value: &str) -> Result<bool, AppError> {
+    let value = value.trim();
+    match name {
+        PresentationConstants::FIELD_MODEL => {
+            options.model = (!value.is_empty()).then(|| value.to_string());
+        }
+        PresentationConstants::FIELD_HIGH_RES => options.high_res = parse_bool(name, value)?,
+        PresentationConstants::FIELD_UPSAMPLING => {
+            options.upsampling = match value.to_ascii_lowercase().as_str() {
+                PresentationConstants::UPSAMPLING_BILINEAR | "" => MaskUpsampling::Bilinear,
+                PresentationConstants::UPSAMPLING_GUIDED => MaskUpsampling::Guided,
+                _ => return Err(invalid_option(name, value)),
+            };
+        }
+        PresentationConstants::FIELD_ALPHA_MATTING => options.alpha_matting = parse_bool(name, value)?,
+        PresentationConstants::FIELD_FOREGROUND_THRESHOLD => options.matting.foreground_threshold = parse_value(name, value)?,
+        PresentationConstants::FIELD_BACKGROUND_THRESHOLD => options.matting.background_threshold = parse_value(name, value)?,
+        PresentationConstants::FIELD_ERODE_SIZE => options.matting.erode_size = parse_value(name, value)?,
+        PresentationConstants::FIELD_DECONTAMINATE => options.decontaminate = parse_bool(name, value)?,
+        PresentationConstants::FIELD_OUTPUT => {
+            options.output = match value.to_ascii_lowercase().as_str() {
+                PresentationConstants::OUTPUT_CUTOUT | "" => OutputMode::Cutout,
+                PresentationConstants::OUTPUT_MASK => OutputMode::Mask,
+                PresentationConstants::OUTPUT_RAW_MASK => OutputMode::RawMask,
+                _ => return Err(invalid_option(name, value)),
+            };
+        }
+        PresentationConstants::FIELD_BACKGROUND_COLOR => {
+            options.background.color = if value.is_empty() { None } else { Some(parse_color(name, value)?) };
+        }
+        PresentationConstants::FIELD_BACKGROUND_GRADIENT => {
+            options.background.gradient = if value.is_empty() { None } else { Some(parse_gradient(name, value)?) };
+        }
+        PresentationConstants::FIELD_BACKGROUND_BLUR => {
+            options.background.blur_radius = if value.is_empty() { None } else { Some(parse_blur_radius(name, value)?) };
+        }
+        PresentationConstants::FIELD_BACKGROUND_FIT => {
+            options.background.fit = match value.to_ascii_lowercase().as_str() {
+                PresentationConstants::FIT_COVER | "" => BackgroundFit::Cover,
+                PresentationConstants::FIT_CONTAIN => BackgroundFit::Contain,
+                _ => return Err(invalid_option(name, value)),
+            };
+        }
+        PresentationConstants::FIELD_FORMAT => {
+            options.encoding.alpha_fallback = None;
+            options.encoding.format = if value.is_empty() {
+                OutputFormat::default()
+            } else {
+                parse_format(value).ok_or_else(|| invalid_option(name, value))?
+            };
+        }
+        PresentationConstants::FIELD_QUALITY => {
+            let quality: u8 = parse_value(name, value)?;
+            if !(1..=100).contains(&quality) {
+                return Err(invalid_option(name, value));
+            }
+            options.encoding.quality = quality;
+        }
+        PresentationConstants::FIELD_LOSSLESS => options.encoding.lossless = parse_bool(name, value)?,
+        PresentationConstants::FIELD_PNG_COMPRESSION => {
+            options.encoding.png_compression = if value.is_empty() { None } else { Some(parse_value(name, value)?) };
+        }
+        PresentationConstants::FIELD_PNG_FILTER => {
+            options.encoding.png_filter = if value.is_empty() { None } else { Some(parse_value(name, value)?) };
+        }
+        PresentationConstants::FIELD_PNG_PALETTE => options.encoding.png_palette = parse_bool(name, value)?,
+        PresentationConstants::FIELD_INPUT_ALPHA => {
+            options.input_alpha = match value.to_ascii_lowercase().as_str() {
+                PresentationConstants::INPUT_ALPHA_MULTIPLY | "" => InputAlpha::Multiply,
+                PresentationConstants::INPUT_ALPHA_MIN => InputAlpha::Min,
+                PresentationConstants::INPUT_ALPHA_IGNORE => InputAlpha::Ignore,
+                _ => return Err(invalid_option(name, value)),
+            };
+        }
+        PresentationConstants::FIELD_COLOR_PROFILE => {
+            options.color_profile = match value.to_ascii_lowercase().as_str() {
+                PresentationConstants::COLOR_PROFILE_KEEP | "" => ColorProfile::Keep,
+                PresentationConstants::COLOR_PROFILE_SRGB => ColorProfile::Srgb,
+                PresentationConstants::COLOR_PROFILE_STRIP => ColorProfile::Strip,
+                _ => return Err(invalid_option(name, value)),
+            };
+        }
+        PresentationConstants::FIELD_KEEP_METADATA => options.keep_metadata = parse_bool(name, value)?,
+        PresentationConstants::FIELD_CROP => options.crop = parse_bool(name, value)?,
+        PresentationConstants::FIELD_CROP_PADDING => options.cropping.padding = parse_padding(name, value)?,
+        PresentationConstants::FIELD_CROP_ASPECT => {
+            options.cropping.aspect_ratio = if value.is_empty() { None } else { Some(parse_aspect_ratio(name, value)?) };
+        }
+        PresentationConstants::FIELD_CROP_THRESHOLD => options.cropping.alpha_threshold = parse_value(name, value)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+pub fn content_type(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Png => PresentationConstants::CONTENT_TYPE_PNG,
+        OutputFormat::WebP => PresentationConstants::CONTENT_TYPE_WEBP,
+        OutputFormat::Jpeg => PresentationConstants::CONTENT_TYPE_JPEG,
+        OutputFormat::Tiff => PresentationConstants::CONTENT_TYPE_TIFF,
+    }
+}
+
+pub fn file_extension(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Png => PresentationConstants::FORMAT_PNG,
+        OutputFormat::WebP => PresentationConstants::FORMAT_WEBP,
+        OutputFormat::Jpeg => PresentationConstants::FORMAT_JPG,
+        OutputFormat::Tiff => PresentationConstants::FORMAT_TIFF,
+    }
+}
+
+fn parse_format(value: &str) -> Option<OutputFormat> {
+    match value.to_ascii_lowercase().as_str() {
+        PresentationConstants::FORMAT_PNG => Some(OutputFormat::Png),
+        PresentationConstants::FORMAT_WEBP => Some(OutputFormat::WebP),
+        PresentationConstants::FORMAT_JPEG | PresentationConstants::FORMAT_JPG => Some(OutputFormat::Jpeg),
+        PresentationConstants::FORMAT_TIFF | PresentationConstants::FORMAT_TIF => Some(OutputFormat::Tiff),
+        _ => None,
+    }
+}
+
+/// Supported image types from the `Accept` header, best first; empty when nothing matches,
+/// leaving PNG as the fallback
+fn formats_from_accept(headers: &HeaderMap) -> Vec<OutputFormat> {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|accept| accept.to_str().ok()) else {
+        return Vec::new();
+    };
+    let mut accepted: Vec<_> = accept
+        .split(',')
+        .enumerate()
+        .filter_map(|(position, entry)| {
+            let mut parts = entry.split(';').map(str::trim);
+            let media_type = parts.next()?.to_ascii_lowercase();
+            let quality = parts
+                .find_map(|parameter| parameter.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let format = match media_type.as_str() {
+                PresentationConstants::CONTENT_TYPE_PNG => OutputFormat::Png,
+                PresentationConstants::CONTENT_TYPE_WEBP => OutputFormat::WebP,
+                PresentationConstants::CONTENT_TYPE_JPEG | PresentationConstants::CONTENT_TYPE_JPG => OutputFormat::Jpeg,
+                PresentationConstants::CONTENT_TYPE_TIFF => OutputFormat::Tiff,
+                _ => return None,
+            };
+            (quality > 0.0).then_some((quality, position, format))
+        })
+        .collect();
+    // Highest q first, ties resolved by order in the header
+    accepted.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    accepted.into_iter().map(|(_, _, format)| format).collect()
+}
+
+pub fn parse_bool(name: &str, value: &str) -> Result<bool, AppError> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" | "" => Ok(false),
+        _ => Err(invalid_option(name, value)),
+    }
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, AppError> {
+    value.parse().map_err(|_| invalid_option(name, value))
+}
+
+fn parse_positive(name: &str, value: &str) -> Result<f32, AppError> {
+    let number: f32 = parse_value(name, value)?;
+    if number.is_finite() && number > 0.0 {
+        Ok(number)
+    } else {
+        Err(invalid_option(name, value))
+    }
+}
+
+fn parse_blur_radius(name: &str, value: &str) -> Result<f32, AppError> {
+    let radius = parse_positive(name, value)?;
+    if radius > MAX_BLUR_RADIUS {
+        return Err(invalid_option(name, value));
+    }
+    Ok(radius)
+}
+
+/// Parses `#rgb` or `#rrggbb`, with or without the leading `#`
+fn parse_color(name: &str, value: &str) -> Result<[u8; 3], AppError> {
+    let hex = value.trim().trim_start_matches('#');
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid_option(name, value));
+
+    match hex.len() {
+        3 if hex.is_ascii() => {
+            let mut color = [0u8; 3];
+            for (c, digit) in color.iter_mut().zip(hex.chars()) {
+                *c = channel(&digit.to_string())? * 17;
+            }
+            Ok(color)
+        }
+        6 if hex.is_ascii() => Ok([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+        _ => Err(invalid_option(name, value)),
+    }
+}
+
+/// Parses `from,to[,angle]`, e.g. `#ffffff,#000000,45`
+fn parse_gradient(name: &str, value: &str) -> Result<Gradient, AppError> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    let (from, to, angle) = match parts.as_slice() {
+        [from, to] => (*from, *to, None),
+        [from, to, angle] => (*from, *to, Some(*angle)),
+        _ => return Err(invalid_option(name, value)),
+    };
+
+    Ok(Gradient {
+        from: parse_color(name, from)?,
+        to: parse_color(name, to)?,
+        angle_degrees: match angle {
+            Some(angle) => parse_value(name, angle)?,
+            None => PresentationConstants::DEFAULT_GRADIENT_ANGLE,
+        },
+    })
+}
+
+/// Parses `10`, `10px` or `5%`
+fn parse_padding(name: &str, value: &str) -> Result<CropPadding, AppError> {
+    if let Some(percent) = value.strip_suffix(PresentationConstants::PADDING_SUFFIX_PERCENT) {
+        let percent: f32 = parse_value(name, percent.trim())?;
+        if !(0.0..=MAX_PADDING_PERCENT).contains(&percent) {
+            return Err(invalid_option(name, value));
+        }
+        return Ok(CropPadding::Percent(percent));
+    }
+
+    let pixels = value.strip_suffix(PresentationConstants::PADDING_SUFFIX_PIXELS).unwrap_or(value).trim();
+    let pixels: u32 = if pixels.is_empty() { 0 } else { parse_value(name, pixels)? };
+    if pixels > MAX_PADDING_PIXELS {
+        return Err(invalid_option(name, value));
+    }
+    Ok(CropPadding::Pixels(pixels))
+}
+
+/// Parses `square`, `width:height` (e.g. `4:3`) or a decimal ratio such as `1.5`
+fn parse_aspect_ratio(name: &str, value: &str) -> Result<f32, AppError> {
+    let ratio = if value.eq_ignore_ascii_case(PresentationConstants::CROP_ASPECT_SQUARE) {
+        1.0
+    } else if let Some((width, height)) = value.split_once(':') {
+        let width: f32 = parse_value(name, width.trim())?;
+        let height: f32 = parse_value(name, height.trim())?;
+        width / height
+    } else {
+        parse_value(name, value)?
+    };
+
+    if (MIN_ASPECT_RATIO..=MAX_ASPECT_RATIO).contains(&ratio) {
+        Ok(ratio)
+    } else {
+        Err(invalid_option(name, value))
+    }
+}
+
+fn invalid_option(name: &str, value: &str) -> AppError {
+    AppError::ImageProcessingError(format!("{} {}: {}", PresentationConstants::ERROR_INVALID_OPTION, name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use super::*;
+
+    fn accept_headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn accept(value: &str) -> Option<OutputFormat> {
+        formats_from_accept(&accept_headers(value)).first().copied()
+    }
+
+    fn negotiated(accept: &str, query: &[(&str, &str)]) -> OutputFormat {
+        let query = query.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect();
+        options_from_request(&accept_headers(accept), &query).unwrap().output_format()
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("bg_color", "#ff8000").unwrap(), [255, 128, 0]);
+        assert_eq!(parse_color("bg_color", "FF8000").unwrap(), [255, 128, 0]);
+        assert_eq!(parse_color("bg_color", " #f80 ").unwrap(), [255, 136, 0]);
+        for value in ["", "#ff80", "#ff800", "#gg8000", "#ff80000", "#ééé", "red"] {
+            assert!(parse_color("bg_color", value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_gradients() {
+        let gradient = parse_gradient("bg_gradient", "#fff, #000000").unwrap();
+        assert_eq!(gradient, Gradient {
+            from: [255, 255, 255],
+            to: [0, 0, 0],
+            angle_degrees: PresentationConstants::DEFAULT_GRADIENT_ANGLE,
+        });
+        assert_eq!(parse_gradient("bg_gradient", "#fff,#000,45").unwrap().angle_degrees, 45.0);
+        for value in ["#fff", "#fff,#000,45,1", "#fff,nope", "#fff,#000,steep"] {
+            assert!(parse_gradient("bg_gradient", value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn picks_format_from_accept() {
+        assert_eq!(accept("image/webp"), Some(OutputFormat::WebP));
+        assert_eq!(accept("image/png;q=0.5, image/jpeg;q=0.9"), Some(OutputFormat::Jpeg));
+        assert_eq!(accept("image/webp, image/png"), Some(OutputFormat::WebP));
+        assert_eq!(accept("IMAGE/TIFF"), Some(OutputFormat::Tiff));
+        assert_eq!(accept("image/webp;q=0, image/png;q=0.1"), Some(OutputFormat::Png));
+        assert_eq!(accept("text/html, */*"), None);
+        assert_eq!(accept("image/webp;q=0"), None);
+        assert!(formats_from_accept(&HeaderMap::new()).is_empty());
+    }
+
+    #[test]
+    fn negotiation_skips_formats_without_alpha_for_cutouts() {
+        assert_eq!(negotiated("image/jpeg, image/png;q=0.9", &[]), OutputFormat::Png);
+        assert_eq!(negotiated("image/jpeg, image/tiff;q=0.5, image/webp;q=0.8", &[]), OutputFormat::WebP);
+        assert_eq!(negotiated("image/jpeg", &[]), OutputFormat::Png);
+        assert_eq!(negotiated("image/jpeg, image/png;q=0.9", &[("bg_color", "#fff")]), OutputFormat::Jpeg);
+        assert_eq!(negotiated("image/jpeg, image/png;q=0.9", &[("output", "mask")]), OutputFormat::Jpeg);
+        // Only an explicit format is kept, so processing refuses it
+        assert_eq!(negotiated("image/png", &[("format", "jpeg")]), OutputFormat::Jpeg);
+    }
+
+    #[test]
+    fn parses_aspect_ratios() {
+        assert_eq!(parse_aspect_ratio("crop_aspect", "square").unwrap(), 1.0);
+        assert_eq!(parse_aspect_ratio("crop_aspect", "4:3").unwrap(), 4.0 / 3.0);
+        assert_eq!(parse_aspect_ratio("crop_aspect", "16 : 9").unwrap(), 16.0 / 9.0);
+        assert_eq!(parse_aspect_ratio("crop_aspect", "1.5").unwrap(), 1.5);
+        for value in ["0:1", "1:0", "20:1", "0.05", "wide", "4:3:2"] {
+            assert!(parse_aspect_ratio("crop_aspect", value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_padding() {
+        assert_eq!(parse_padding("crop_padding", "10").unwrap(), CropPadding::Pixels(10));
+        assert_eq!(parse_padding("crop_padding", "10px").unwrap(), CropPadding::Pixels(10));
+        assert_eq!(parse_padding("crop_padding", "5%").unwrap(), CropPadding::Percent(5.0));
+        assert_eq!(parse_padding("crop_padding", "0").unwrap(), CropPadding::Pixels(0));
+        for value in ["-1", "2049", "101%", "-5%", "ten", "10em"] {
+            assert!(parse_padding("crop_padding", value).is_err(), "{}", value);
+        }
+    }
+}
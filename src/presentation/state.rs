@@ -0,0 +1,31 @@
+use std::sync::Arc;
+use axum::extract::FromRef;
+use crate::application::image_fetcher::ImageFetcher;
+use crate::application::image_processor::ImageProcessor;
+use crate::application::jobs::JobManager;
+
+/// Shared handler state; handlers extract only the parts they use
+#[derive(Clone)]
+pub struct AppState {
+    pub processor: Arc<ImageProcessor>,
+    pub fetcher: Arc<ImageFetcher>,
+    pub jobs: Arc<JobManager>,
+}
+
+impl FromRef<AppState> for Arc<ImageProcessor> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.processor)
+    }
+}
+
+impl FromRef<AppState> for Arc<ImageFetcher> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.fetcher)
+    }
+}
+
+impl FromRef<AppState> for Arc<JobManager> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.jobs)
+    }
+}